@@ -31,8 +31,13 @@ export async function genExtSrcData(
     fiberRadius,
     entrancePupilHalfDiameter(source),
     refocus,
-    rustStruct
+    Float64Array.from(source.wavelengths),
+    rustStruct,
+    null, // on axis field
+    undefined, // random sampling
+    undefined // new seed
   )
+  if (rays === undefined) throw Error('Lens has no index at the source wavelength')
   const pVecs = new Float64Array(memory.buffer, rays.pPtr, rays.pSize)
   //console.timeEnd('genExtSrcData: WASM raytrace')
   return pVecs
@@ -1,23 +1,18 @@
 import type Lens from '$lib/lens'
-import { trace3DRay } from '$lib/raytrace'
-import { Vector3D } from '$lib/vector'
+import type { LightSource } from '../lightSource'
+import { curvePoints, genRayAberrations } from './rayfans'
 
-export function genLSAData(
+// axial crossing relative to the image plane against pupil height, from the axis to halfCa
+export async function genLSAData(
   lens: Lens,
-  wavelength: number,
+  source: LightSource,
   refocus: number,
   halfCa: number,
   gridSize: number
-): number[][] {
-  const zeroDir = new Vector3D(0.0, 0.0, 1.0)
-  const inc = halfCa / (gridSize - 1)
+): Promise<number[][]> {
+  const aberrations = await genRayAberrations(lens, source, refocus, halfCa, 2 * gridSize - 1)
+  if (aberrations === null) return []
 
-  const data: number[][] = []
-
-  for (let row = 0; row < gridSize; row++) {
-    const y = row * inc
-    const rayout = trace3DRay(new Vector3D(0.0, y, 0.0), zeroDir, lens, refocus, wavelength)
-    data.push([y, rayout.pVector.y])
-  }
-  return data
+  const { height, curves } = aberrations.axial
+  return curvePoints(height, curves[0].lsa).filter(([y]) => y >= 0)
 }
@@ -0,0 +1,61 @@
+import type Lens from '$lib/lens'
+import { serializeToRustStruct } from '$lib/lens'
+import init, { rayAberrations } from '$tracer'
+import type { LightSource } from '../lightSource'
+
+// Ray aberration curves from the wasm tracer. Pupil positions are in lens units on the z = 0
+// plane, transverse aberrations and LSA in lens units and OPD in waves. A ray that failed
+// leaves null at its pupil position.
+export interface AxialCurve {
+  wavelength: number
+  tsa: (number | null)[]
+  lsa: (number | null)[]
+}
+
+export interface RayFan {
+  wavelength: number
+  tangential: (number | null)[]
+  sagittal: (number | null)[]
+  opd_tangential: (number | null)[]
+  opd_sagittal: (number | null)[]
+}
+
+export interface FieldFans {
+  field: Record<string, { x: number; y: number }>
+  pupil: number[]
+  fans: RayFan[]
+}
+
+export interface RayAberrations {
+  axial: { height: number[]; curves: AxialCurve[] }
+  fields: FieldFans[]
+}
+
+// TSA, LSA and the ray and OPD fans of each field at every wavelength of the source. fields are
+// tracer field payloads such as { ObjectAngle: { x: 0, y: 0.1 } }, on axis when left out.
+export async function genRayAberrations(
+  lens: Lens,
+  source: LightSource,
+  refocus: number,
+  pupilRadius: number,
+  numPoints: number,
+  fields?: FieldFans['field'][]
+): Promise<RayAberrations | null> {
+  await init()
+  return rayAberrations(
+    numPoints,
+    pupilRadius,
+    refocus,
+    Float64Array.from(source.wavelengths),
+    serializeToRustStruct(lens, source),
+    fields
+  )
+}
+
+// [x, y] pairs of a curve, leaving out the failed rays
+export function curvePoints(xs: number[], ys: (number | null)[]): number[][] {
+  return xs.flatMap((x, i) => {
+    const y = ys[i]
+    return y === null ? [] : [[x, y]]
+  })
+}
@@ -1,10 +1,22 @@
 import type Lens from '$lib/lens'
 import { serializeToRustStruct } from '$lib/lens'
-import init, { genPSFLine, genGaussLine, runWASMRaytrace, PSFResult, TraceResults } from '$tracer'
+import init, {
+  genPSFLine,
+  genGaussLine,
+  runWASMRaytrace,
+  OpdMode,
+  PSFResult,
+  TraceResults,
+} from '$tracer'
 import { cullVector3DData } from '../ThreeGutils'
 import { fittofermiDirac } from '../fermi'
 import { processRustRayData } from '../gUtils'
-import { entrancePupilHalfDiameter, LightSourceKind, type LightSource } from '../lightSource'
+import {
+  entrancePupilHalfDiameter,
+  LightSourceKind,
+  wavelengthWeights,
+  type LightSource,
+} from '../lightSource'
 
 const defaultarray = [
   [0.0, 0.0336],
@@ -56,10 +68,13 @@ export async function simFocusData(
       const psfresult: PSFResult = genPSFLine(
         pupilWidth,
         outerGrid,
-        source.wavelengths[0],
+        Float64Array.from(source.wavelengths),
+        Float64Array.from(wavelengthWeights(source)),
         entrancePupilHalfDiameter(source),
         refocus,
-        serializeToRustStruct(lens, source)
+        OpdMode.OpticalPath,
+        serializeToRustStruct(lens, source),
+        null // on axis field
       )
       if (psfresult === undefined) return defaultarray
       return rustPSFToData(
@@ -75,11 +90,14 @@ export async function simFocusData(
       const psfresult: PSFResult = genGaussLine(
         pupilWidth,
         outerGrid,
-        source.wavelengths[0],
+        Float64Array.from(source.wavelengths),
+        Float64Array.from(wavelengthWeights(source)),
         entrancePupilHalfDiameter(source),
         source.e2halfDiameter,
         refocus,
-        serializeToRustStruct(lens, source)
+        OpdMode.OpticalPath,
+        serializeToRustStruct(lens, source),
+        null // on axis field
       )
       if (psfresult === undefined) return defaultarray
       return rustPSFToData(
@@ -103,11 +121,23 @@ export async function simFocusData(
         imagesize,
         entrancePupilHalfDiameter(source),
         refocus,
-        serializeToRustStruct(lens, source)
+        Float64Array.from(source.wavelengths),
+        serializeToRustStruct(lens, source),
+        null, // on axis field
+        undefined, // random sampling
+        undefined // new seed
       )
       if (rays === undefined) return defaultarray
+      const weights = wavelengthWeights(source)
+      const total = weights.reduce((a, b) => a + b, 0)
+      const rayWeights = Array.from(
+        new Float64Array(memory.buffer, rays.wPtr, rays.wSize),
+        // equal weights when none is set, as the tracer does
+        (w) => (total > 0 ? weights[source.wavelengths.indexOf(w)] / total : 1 / weights.length)
+      )
       return rustExtSrcToData(
         new Float64Array(memory.buffer, rays.pPtr, rays.pSize),
+        rayWeights,
         imagesize,
         numPositions,
         numAngles,
@@ -129,6 +159,7 @@ export async function simFocusData(
 // condense the psf float64array from rust function to data[][] array
 function rustExtSrcToData(
   peVecs: Float64Array,
+  rayWeights: number[],
   imagesize: number,
   numPositions: number,
   numAngles: number,
@@ -142,7 +173,7 @@ function rustExtSrcToData(
     ((sbins - 1) / (multiplier * 2) - 1 / Math.sqrt(2))
   //console.log ('cell, vscale', cellSize, vscale, vscale2)
 
-  const [datamap, errors] = processRustRayData(peVecs, imagesize, sbins, multiplier)
+  const [datamap, errors] = processRustRayData(peVecs, imagesize, sbins, multiplier, rayWeights)
   console.log('extended errors', errors)
 
   const [xs, ys] = cullVector3DData(datamap, cellSize, vscale, numPositions * numAngles)
@@ -1,7 +1,6 @@
 import type Lens from '$lib/lens'
-import { trace3DRay } from '$lib/raytrace'
-import { Vector3D } from '$lib/vector'
 import { entrancePupilHalfDiameter, type LightSource } from '../lightSource'
+import { curvePoints, genRayAberrations } from './rayfans'
 
 // use this dummy array if something is wrong with source or lens
 const defaultarray = [
@@ -12,35 +11,25 @@ const defaultarray = [
   [10, 10],
 ]
 
-export function genTSAData(
+export async function genTSAData(
   lens: Lens,
   source: LightSource | undefined,
   refocus: number,
-  gridSize = 31
-): number[][] {
+  gridSize = 61
+): Promise<number[][]> {
   if (!source) return defaultarray
   if (!Number.isFinite(lens.EFL(source.wavelengths[0]))) {
     throw Error("Lens doesn't have a finite EFL")
   }
-  const zeroDir = new Vector3D(0.0, 0.0, 1.0)
-  const inc = entrancePupilHalfDiameter(source) / (gridSize - 1)
-  const data: number[][] = []
+  const aberrations = await genRayAberrations(
+    lens,
+    source,
+    refocus,
+    entrancePupilHalfDiameter(source),
+    gridSize
+  )
+  if (aberrations === null) return defaultarray
 
-  for (let row = 0; row < gridSize; row++) {
-    const y = row * inc
-    const rayout = trace3DRay(
-      new Vector3D(0.0, y, 0.0),
-      zeroDir,
-      lens,
-      refocus,
-      source.wavelengths[0]
-    )
-    data.push([y, rayout.pVector.y])
-    if (row !== 0) {
-      data.push([-y, -rayout.pVector.y]) // need to invert both x and y
-    }
-  }
-  data.sort((a, b) => a[0] - b[0])
-
-  return data
+  const { height, curves } = aberrations.axial
+  return curvePoints(height, curves[0].tsa)
 }
@@ -1,8 +1,10 @@
 import type Lens from './lens'
+import { serializeToRustStruct } from './lens'
 import { Vector3D } from './vector'
 import { trace3DRay } from './raytrace'
 import type { Ray } from './raytrace'
-import { entrancePupilHalfDiameter, type LightSource } from './lightSource'
+import { entrancePupilHalfDiameter, wavelengthWeights, type LightSource } from './lightSource'
+import init, { spotDiagram } from '$tracer'
 
 const zeroDir = new Vector3D(0.0, 0.0, 1.0)
 //import Decimal from 'decimal.js'
@@ -161,6 +163,57 @@ export function CalcRMSSpotSize(spots: Ray[]): BasicStats {
   return eStat
 }
 
+// spot diagram results of the wasm tracer, positions and radii in lens units on the image plane
+// of the first wavelength, radii measured from the centroid
+export interface SpotStats {
+  centroid_x: number
+  centroid_y: number
+  rms_radius: number
+  geo_radius: number
+  rms_x: number
+  rms_y: number
+  num_rays: number
+}
+
+export interface WavelengthSpot {
+  wavelength: number
+  airy_radius: number
+  stats: SpotStats | null
+  points: [number, number][]
+  num_failed: number
+}
+
+export interface FieldSpot {
+  field: Record<string, { x: number; y: number }>
+  wavelengths: WavelengthSpot[]
+  polychromatic: SpotStats | null
+}
+
+// GenSpotDiagram and CalcRMSSpotSize for every field and wavelength of the source in one call.
+// fields are tracer field payloads such as { ObjectAngle: { x: 0, y: 0.1 } }, on axis when
+// left out.
+export async function spotDiagramWasm(
+  lens: Lens,
+  source: LightSource,
+  refocus: number,
+  numRays: number,
+  fields?: FieldSpot['field'][]
+): Promise<FieldSpot[]> {
+  await init()
+  const spots = spotDiagram(
+    numRays,
+    entrancePupilHalfDiameter(source),
+    refocus,
+    Float64Array.from(source.wavelengths),
+    Float64Array.from(wavelengthWeights(source)),
+    serializeToRustStruct(lens, source),
+    fields,
+    undefined,
+    undefined
+  )
+  return spots ?? []
+}
+
 // *************************************************************************
 // all functions below would normally not be exported, but they now are so
 // that the test1.ts main program can debug or vet their results.
@@ -181,7 +181,8 @@ export function processRustRayData(
   vlist: Vector3D[] | Float64Array,
   fiber_radius: number,
   sbins: number,
-  multiplier = 3
+  multiplier = 3,
+  rayWeights?: number[] // weight of each ray, 1 when not given
 ): [number[][], number] {
   const minbin = -multiplier * fiber_radius
   const maxbin = multiplier * fiber_radius
@@ -197,7 +198,7 @@ export function processRustRayData(
     const row = Math.round((Number(vlist[i]) - minbin) / binsize)
     const col = Math.round((Number(vlist[i + 1]) - minbin) / binsize)
     if (row >= 0 && row < sbins && col >= 0 && col < sbins) {
-      indata[row][col] = indata[row][col] + 1
+      indata[row][col] = indata[row][col] + (rayWeights?.[i / 3] ?? 1)
     } else {
       errors++
     }
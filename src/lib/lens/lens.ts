@@ -208,6 +208,7 @@ export function serializeToRustStruct(lens: Lens, source: LightSource) {
     clear_ap: Math.min(lens.surf1.ap, lens.surf2.ap),
     ct: lens.ct,
     n_index: lens.material.nIndexAt(source.wavelengths[0]),
+    material: lens.material.toString(),
     side1: lens.surf1.toRustStruct(),
     side2: lens.surf2.toRustStruct(),
   }
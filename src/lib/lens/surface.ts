@@ -131,8 +131,7 @@ export default class Surface {
       r: this.r,
       c: this.c,
       k: this.k,
-      ad: this.asphericTerms.coeffAt(0),
-      ae: this.asphericTerms.coeffAt(1),
+      coeffs: [...this.asphericTerms.coeffs],
       surf_type: this.type === 'plane' ? 0 : this.type === 'sphere' ? 1 : 2,
     }
   }
@@ -8,6 +8,8 @@ export enum LightSourceKind {
 interface LightSourceBase {
   kind: LightSourceKind
   wavelengths: number[]
+  // relative weight of each wavelength, missing weights count as 1
+  weights?: number[]
 }
 
 export interface CollimatedFlattop extends LightSourceBase {
@@ -52,6 +54,10 @@ export function makeExtendedSorce(halfDiameter: number, wavelengths: number | nu
   } as ExtendedSource
 }
 
+export function wavelengthWeights(source: LightSource) {
+  return source.wavelengths.map((_, i) => source.weights?.[i] ?? 1)
+}
+
 export function entrancePupilHalfDiameter(source: LightSource) {
   switch (source.kind) {
     case LightSourceKind.CollimatedFlattop:
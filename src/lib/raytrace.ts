@@ -1,5 +1,7 @@
 import type Lens from './lens'
 import type { Surface } from './lens'
+import { serializeToRustStruct } from './lens'
+import init, { traceRayPaths } from '$tracer'
 import type { LightSource } from './lightSource'
 import { Vector3D } from './vector'
 
@@ -234,6 +236,37 @@ export function trace3DRay(
   return { pVector: P4, eDir: E3 }
 }
 
+// trace3DRayPath for a whole bundle through the wasm tracer. Each path holds the launch point,
+// the surface intercepts and the image point, a vignetted ray ends where it was stopped.
+export async function traceRayPathsWasm(
+  rays: Ray[],
+  lens: Lens,
+  source: LightSource,
+  refocus: number
+): Promise<Vector3D[][]> {
+  const { memory } = await init()
+  const flat = Float64Array.from(
+    rays.flatMap((r) => [r.pVector.x, r.pVector.y, r.pVector.z, r.eDir.x, r.eDir.y, r.eDir.z])
+  )
+  const paths = traceRayPaths(
+    flat,
+    refocus,
+    source.wavelengths[0],
+    serializeToRustStruct(lens, source)
+  )
+  if (paths === undefined) return []
+
+  const points = new Float64Array(memory.buffer, paths.pointsPtr, paths.pointsSize)
+  const lengths = new Uint32Array(memory.buffer, paths.lengthsPtr, paths.lengthsSize)
+  const stride = paths.stride
+  return Array.from(lengths, (length, i) =>
+    Array.from({ length }, (_, j) => {
+      const k = 3 * (i * stride + j)
+      return new Vector3D(points[k], points[k + 1], points[k + 2])
+    })
+  )
+}
+
 export function trace3DRayPath(
   P0: Vector3D,
   E0: Vector3D,
@@ -0,0 +1,29 @@
+import init, { parseZmx, writeZmx } from '$tracer'
+
+// ZMX import and export through the tracer, the same parser the batch tools use. Warnings list
+// what the prescription uses that the tracer cannot represent.
+export interface NativeZmx {
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  system: any
+  warnings: unknown[]
+}
+
+export async function importZmx(text: string): Promise<NativeZmx> {
+  await init()
+  const result = parseZmx(text)
+  if (result === null) {
+    throw new Error('Could not convert file to lens prescription!')
+  }
+  return result
+}
+
+export async function exportZmx(
+  system: NativeZmx['system']
+): Promise<{ text: string; warnings: unknown[] }> {
+  await init()
+  const result = writeZmx(system)
+  if (result === null) {
+    throw new Error('Lens has no surfaces to export!')
+  }
+  return result
+}
@@ -0,0 +1,266 @@
+// Parser for Zemax AGF glass catalogs. Each glass starts with an NM record and collects the
+// records that follow it:
+// NM name formula mil nd vd ...   CD dispersion coefficients   TD thermal data
+// LD min max wavelength           IT wavelength transmission thickness
+// Other records (CC, GC, ED, OD, ...) are skipped.
+
+use crate::material::{Formula, Material, Thermal, TransmissionSample};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgfError {
+    // a glass record before the first NM line
+    NoGlass { line: usize },
+    BadNumber { line: usize },
+    UnsupportedFormula { glass: String, formula: u32 },
+    MissingCoefficients { glass: String },
+}
+
+// glass being parsed, turned into a Material at the next NM record or the end of the file
+struct Entry {
+    name: String,
+    formula: u32,
+    coeffs: Vec<f64>,
+    range: Option<(f64, f64)>,
+    thermal: Option<Thermal>,
+    transmission: Vec<TransmissionSample>,
+}
+
+// Parse a catalog into its glasses. A glass that cannot be used (an unsupported formula or
+// missing coefficients) is reported and the rest of the catalog is still returned.
+pub fn parse_agf(text: &str, source: &str) -> (Vec<Material>, Vec<AgfError>) {
+    let mut glasses = vec![];
+    let mut errors = vec![];
+    let mut entry: Option<Entry> = None;
+
+    for (i, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line_number = i + 1;
+        let mut fields = line.split_whitespace();
+        let Some(record) = fields.next() else {
+            continue;
+        };
+        let fields = fields.collect::<Vec<&str>>();
+
+        if record == "NM" {
+            if let Some(done) = entry.take() {
+                push_glass(done, source, &mut glasses, &mut errors);
+            }
+            let Some(name) = fields.first() else {
+                errors.push(AgfError::BadNumber { line: line_number });
+                continue;
+            };
+            // the formula number is written as an integer or a float depending on the tool
+            match fields.get(1).and_then(|f| f.parse::<f64>().ok()) {
+                Some(formula) => {
+                    entry = Some(Entry {
+                        name: name.to_string(),
+                        formula: formula as u32,
+                        coeffs: vec![],
+                        range: None,
+                        thermal: None,
+                        transmission: vec![],
+                    })
+                }
+                None => errors.push(AgfError::BadNumber { line: line_number }),
+            }
+            continue;
+        }
+
+        if !matches!(record, "CD" | "TD" | "LD" | "IT") {
+            continue;
+        }
+        let Some(glass) = entry.as_mut() else {
+            errors.push(AgfError::NoGlass { line: line_number });
+            continue;
+        };
+        let Ok(values) = fields
+            .iter()
+            .map(|f| f.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+        else {
+            errors.push(AgfError::BadNumber { line: line_number });
+            continue;
+        };
+
+        match (record, values.as_slice()) {
+            ("CD", _) => glass.coeffs = values,
+            ("TD", [d0, d1, d2, e0, e1, lambda_tk, reference_temperature, ..]) => {
+                glass.thermal = Some(Thermal {
+                    d0: *d0,
+                    d1: *d1,
+                    d2: *d2,
+                    e0: *e0,
+                    e1: *e1,
+                    lambda_tk: *lambda_tk,
+                    reference_temperature: *reference_temperature,
+                })
+            }
+            ("LD", [min, max, ..]) => glass.range = Some((*min, *max)),
+            // some catalogs write empty IT records
+            ("IT", [wavelength, transmission, thickness, ..]) => {
+                glass.transmission.push(TransmissionSample {
+                    wavelength: *wavelength,
+                    transmission: *transmission,
+                    thickness: *thickness,
+                })
+            }
+            ("IT", _) => {}
+            _ => errors.push(AgfError::BadNumber { line: line_number }),
+        }
+    }
+    if let Some(done) = entry.take() {
+        push_glass(done, source, &mut glasses, &mut errors);
+    }
+    (glasses, errors)
+}
+
+fn push_glass(entry: Entry, source: &str, glasses: &mut Vec<Material>, errors: &mut Vec<AgfError>) {
+    match formula(&entry) {
+        Ok(formula) => {
+            let (min, max) = entry.range.unwrap_or((0.0, f64::INFINITY));
+            let mut glass = Material::new(&entry.name, min, max, source, formula);
+            glass.thermal = entry.thermal;
+            glass.transmission = entry.transmission;
+            glass
+                .transmission
+                .sort_by(|a, b| a.wavelength.total_cmp(&b.wavelength));
+            glasses.push(glass);
+        }
+        Err(err) => errors.push(err),
+    }
+}
+
+// dispersion formula from the AGF formula number and CD coefficients
+fn formula(entry: &Entry) -> Result<Formula, AgfError> {
+    let needed = match entry.formula {
+        1..=3 => 6,
+        4 => 5,
+        5 => 3,
+        6 => 8,
+        9 => 5,
+        11 => 10,
+        formula => {
+            return Err(AgfError::UnsupportedFormula {
+                glass: entry.name.clone(),
+                formula,
+            })
+        }
+    };
+    if entry.coeffs.len() < needed {
+        return Err(AgfError::MissingCoefficients {
+            glass: entry.name.clone(),
+        });
+    }
+
+    let c = &entry.coeffs[..needed];
+    let pairs = |c: &[f64]| c.chunks(2).map(|kl| (kl[0], kl[1])).collect();
+    Ok(match entry.formula {
+        1 => Formula::Schott(c.to_vec()),
+        3 => Formula::Herzberger(c.to_vec()),
+        4 => Formula::Sellmeier2 {
+            a: c[0],
+            b1: c[1],
+            l1: c[2],
+            b2: c[3],
+            l2: c[4],
+        },
+        5 => Formula::Conrady(c.to_vec()),
+        // Sellmeier 4: n^2 = a + b l^2 / (l^2 - c) + d l^2 / (l^2 - e)
+        9 => Formula::Sellmeier {
+            a: c[0],
+            terms: pairs(&c[1..]),
+        },
+        // Sellmeier 1, 3 and 5 only differ in the number of terms
+        _ => Formula::Sellmeier {
+            a: 1.0,
+            terms: pairs(c),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lens::tests::bk7_singlet;
+    use crate::material::{self, MaterialError};
+
+    const CATALOG: &str = "\u{feff}CC Test catalog
+NM N-BK7 2 517642.251 1.5168 64.17 0 1
+GC
+ED 7.1 8.3 2.51 -0.0009 0
+CD 1.03961212E+00 6.00069867E-03 2.31792344E-01 2.00179144E-02 1.01046945E+00 1.03560653E+02 0 0 0 0
+TD 1.86E-06 1.31E-08 -1.37E-11 4.34E-07 6.27E-10 1.70E-01 2.00E+01
+OD 1 1 0 1 1 2.3
+LD 3.00E-01 2.50E+00
+IT 3.50E-01 9.40E-01 2.50E+01
+IT 4.00E-01 9.97E-01 2.50E+01
+IT
+NM SCHOTTFORM 1.0 0 1.5 60 0 1
+CD 2.27 -0.01 0.01 0 0 0
+LD 0.4 1.0
+NM CONRADY 5 0 1.5 60 0 1
+CD 1.5 0.01 0.001
+NM EXTENDED 13 0 1.5 60 0 1
+CD 1 2 3 4 5 6 7 8 9
+";
+
+    #[test]
+    fn parses_glasses() {
+        let (glasses, errors) = parse_agf(CATALOG, "test");
+        assert_eq!(
+            errors,
+            vec![AgfError::UnsupportedFormula {
+                glass: "EXTENDED".to_string(),
+                formula: 13
+            }]
+        );
+        assert_eq!(glasses.len(), 3);
+
+        let bk7 = &glasses[0];
+        assert!((bk7.n_index_at(0.5875618).unwrap() - 1.5168).abs() < 1e-4);
+        assert_eq!((bk7.min_wavelength, bk7.max_wavelength), (0.3, 2.5));
+        assert_eq!(bk7.transmission.len(), 2);
+
+        // n^2 = 2.27 - 0.01 l^2 + 0.01 / l^2 at 0.5
+        let schott = glasses[1].n_index_at(0.5).unwrap();
+        assert!((schott - (2.27 - 0.0025 + 0.04_f64).sqrt()).abs() < 1e-12);
+        assert!(glasses[1].n_index_at(1.5).is_err());
+
+        let conrady = glasses[2].n_index_at(1.0).unwrap();
+        assert!((conrady - 1.511).abs() < 1e-12);
+    }
+
+    #[test]
+    fn thermal_and_transmission() {
+        let (glasses, _) = parse_agf(CATALOG, "test");
+        let bk7 = &glasses[0];
+
+        let n = bk7.n_index_at(0.55).unwrap();
+        assert_eq!(bk7.n_index_at_temperature(0.55, 20.0).unwrap(), n);
+        let dndt = (bk7.n_index_at_temperature(0.55, 21.0).unwrap() - n) / 1.0;
+        assert!(dndt > 1e-6 && dndt < 3e-6);
+
+        // the sample thickness reproduces the table, twice as thick squares it
+        assert!((bk7.internal_transmission(0.35, 25.0).unwrap() - 0.94).abs() < 1e-12);
+        assert!((bk7.internal_transmission(0.35, 50.0).unwrap() - 0.94 * 0.94).abs() < 1e-12);
+        assert_eq!(bk7.internal_transmission(0.3, 25.0), None);
+        assert_eq!(glasses[1].internal_transmission(0.5, 10.0), None);
+    }
+
+    #[test]
+    fn registered_glass_is_a_lens_medium() {
+        let (glasses, _) = parse_agf(CATALOG, "test");
+        material::register(glasses);
+
+        let mut lens = bk7_singlet();
+        lens.surfaces[0].n_index = 1.0;
+        lens.surfaces[0].material = Some("n-bk7".to_string());
+        let lens = lens.at_wavelength(0.5875618).unwrap();
+        assert!((lens.surfaces[0].n_index - 1.5168).abs() < 1e-4);
+        assert!(material::names().contains(&"N-BK7".to_string()));
+
+        assert_eq!(
+            Material::from_name("N-SF6"),
+            Err(MaterialError::Unknown("N-SF6".to_string()))
+        );
+    }
+}
@@ -1,3 +1,7 @@
+use crate::material::{Material, MaterialError};
+use crate::paraxial::{self, ParaxialRay};
+use crate::qpoly::{qbfs_sum, qcon_sum};
+use crate::raytrace::{odd_poly_sag, odd_poly_slope, ray_vector::Vector3D, IntersectConfig};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -5,35 +9,245 @@ pub enum SurfaceType {
     Plane,
     Sphere,
     Asphere,
+    OddAsphere,
+    Cylinder,
+    Toroid,
+    Biconic,
+    Freeform,
 }
 
+// How the asphere coefficients of a side are interpreted.
+// Standard: coeffs[i] multiplies r^(4 + 2i)
+// Qcon, Qbfs: coeffs[m] multiplies the m-th Forbes polynomial over norm_radius, see qpoly.rs.
+// Qbfs departs from the best fit sphere, so k is expected to be zero.
+// Odd: coeffs[i] multiplies r^(i + 1), covering axicons (r^1) and odd aspheres (r^3, r^5, ...).
+// Biconic: r and k act in x, ry and ky in y, coeffs are the standard rotational terms.
+// Toroid: the y profile (ry, ky and coeffs as standard terms in y) is swept about an axis
+// parallel to y at radius r. k is unused. Either form with one flat radius is a cylinder.
+// XyPolynomial, Zernike: freeform terms over norm_radius added to the conic, see
+// raytrace/freeform.rs for the term ordering.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum AsphereDefinition {
+    #[default]
+    Standard,
+    Qcon {
+        norm_radius: f64,
+    },
+    Qbfs {
+        norm_radius: f64,
+    },
+    Odd,
+    Biconic {
+        ry: f64,
+        ky: f64,
+    },
+    Toroid {
+        ry: f64,
+        ky: f64,
+    },
+    XyPolynomial {
+        norm_radius: f64,
+    },
+    Zernike {
+        norm_radius: f64,
+    },
+}
+
+// Conic base with an asphere polynomial. For the standard definition the first two
+// coefficients are the original ad (r^4) and ae (r^6) terms.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SidePayload")]
 pub struct Side {
     pub r: f64,
     pub k: f64,
-    pub ad: f64,
-    pub ae: f64,
+    pub coeffs: Vec<f64>,
+    pub definition: AsphereDefinition,
+}
+
+// older payloads only carry the r^4 and r^6 terms as ad and ae
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SidePayload {
+    Polynomial {
+        r: f64,
+        k: f64,
+        coeffs: Vec<f64>,
+        #[serde(default)]
+        definition: AsphereDefinition,
+    },
+    Legacy {
+        r: f64,
+        k: f64,
+        ad: f64,
+        ae: f64,
+    },
+}
+
+impl From<SidePayload> for Side {
+    fn from(payload: SidePayload) -> Self {
+        match payload {
+            SidePayload::Polynomial {
+                r,
+                k,
+                coeffs,
+                definition,
+            } => Side {
+                r,
+                k,
+                coeffs,
+                definition,
+            },
+            SidePayload::Legacy { r, k, ad, ae } => Side::new(r, k, vec![ad, ae]),
+        }
+    }
 }
 
 impl Side {
-    pub fn new(r: f64, k: f64, ad: f64, ae: f64) -> Side {
-        Side { r, k, ad, ae }
+    pub fn new(r: f64, k: f64, coeffs: Vec<f64>) -> Side {
+        Side {
+            r,
+            k,
+            coeffs,
+            definition: AsphereDefinition::Standard,
+        }
     }
 
     pub fn surf_type(&self) -> SurfaceType {
-        if f64::abs(self.r) < 0.01
-            && f64::abs(self.k) < 1e-8
-            && f64::abs(self.ad) < 1e-20
-            && f64::abs(self.ae) < 1e-20
-        {
+        let has_poly = self.coeffs.iter().any(|a| f64::abs(*a) >= 1e-20);
+        let flat = |r: f64| f64::abs(r) < 0.01;
+        match self.definition {
+            AsphereDefinition::Biconic { ry, ky } => {
+                let no_conic = f64::abs(self.k) < 1e-8 && f64::abs(ky) < 1e-8;
+                return match (flat(self.r), flat(ry)) {
+                    (true, true) if no_conic && !has_poly => SurfaceType::Plane,
+                    (true, false) | (false, true) if no_conic && !has_poly => SurfaceType::Cylinder,
+                    _ => SurfaceType::Biconic,
+                };
+            }
+            AsphereDefinition::Toroid { ry, ky } => {
+                let plain_profile = f64::abs(ky) < 1e-8 && !has_poly;
+                return match (flat(self.r), flat(ry)) {
+                    (true, true) if plain_profile => SurfaceType::Plane,
+                    (true, false) | (false, true) if plain_profile => SurfaceType::Cylinder,
+                    _ => SurfaceType::Toroid,
+                };
+            }
+            _ => (),
+        }
+
+        if f64::abs(self.r) < 0.01 && f64::abs(self.k) < 1e-8 && !has_poly {
             SurfaceType::Plane
-        } else if f64::abs(self.ad) < 1e-20 && f64::abs(self.ae) < 1e-20 {
+        } else if !has_poly {
             SurfaceType::Sphere
+        } else if self.definition == AsphereDefinition::Odd {
+            SurfaceType::OddAsphere
+        } else if !self.is_rotational() {
+            SurfaceType::Freeform
         } else {
             SurfaceType::Asphere
         }
     }
 
+    pub fn is_rotational(&self) -> bool {
+        !matches!(
+            self.definition,
+            AsphereDefinition::Biconic { .. }
+                | AsphereDefinition::Toroid { .. }
+                | AsphereDefinition::XyPolynomial { .. }
+                | AsphereDefinition::Zernike { .. }
+        )
+    }
+
+    // conic part of the sag, r2 is x^2 + y^2, None beyond the edge of the conic as in calc_sag
+    pub fn conic_sag(&self, r2: f64) -> Option<f64> {
+        let c = self.curv();
+        let sqrtvalue = 1.0 - (1.0 + self.k) * c * c * r2;
+        if sqrtvalue < 0.0 {
+            None
+        } else {
+            Some(c * r2 / (1.0 + sqrtvalue.sqrt()))
+        }
+    }
+
+    // polynomial part of the sag, r2 is x^2 + y^2
+    pub fn poly_sag(&self, r2: f64) -> f64 {
+        match self.definition {
+            AsphereDefinition::Standard
+            | AsphereDefinition::Biconic { .. }
+            | AsphereDefinition::Toroid { .. } => {
+                let mut rpow = r2;
+                let mut sag = 0.0;
+                for a in &self.coeffs {
+                    rpow *= r2;
+                    sag += a * rpow;
+                }
+                sag
+            }
+            AsphereDefinition::Qcon { norm_radius } => {
+                let x = r2 / (norm_radius * norm_radius);
+                x * x * qcon_sum(&self.coeffs, x).0
+            }
+            AsphereDefinition::Qbfs { norm_radius } => {
+                let x = r2 / (norm_radius * norm_radius);
+                let c = self.curv();
+                x * (1.0 - x) * qbfs_sum(&self.coeffs, x).0 / (1.0 - c * c * r2).sqrt()
+            }
+            AsphereDefinition::Odd => odd_poly_sag(r2.sqrt(), &self.coeffs),
+            // freeform terms depend on x and y separately, calc_sag adds them
+            AsphereDefinition::XyPolynomial { .. } | AsphereDefinition::Zernike { .. } => 0.0,
+        }
+    }
+
+    // derivative of poly_sag with respect to r2
+    pub fn poly_slope(&self, r2: f64) -> f64 {
+        match self.definition {
+            AsphereDefinition::Standard
+            | AsphereDefinition::Biconic { .. }
+            | AsphereDefinition::Toroid { .. } => {
+                let mut rpow = 1.0;
+                let mut slope = 0.0;
+                for (i, a) in self.coeffs.iter().enumerate() {
+                    rpow *= r2;
+                    slope += (i + 2) as f64 * a * rpow;
+                }
+                slope
+            }
+            AsphereDefinition::Qcon { norm_radius } => {
+                let rn2 = norm_radius * norm_radius;
+                let x = r2 / rn2;
+                let (q, dq) = qcon_sum(&self.coeffs, x);
+                (2.0 * x * q + x * x * dq) / rn2
+            }
+            AsphereDefinition::Qbfs { norm_radius } => {
+                let rn2 = norm_radius * norm_radius;
+                let x = r2 / rn2;
+                let c = self.curv();
+                let (q, dq) = qbfs_sum(&self.coeffs, x);
+                let phi = 1.0 - c * c * r2;
+                ((1.0 - 2.0 * x) * q + x * (1.0 - x) * dq) / (rn2 * phi.sqrt())
+                    + x * (1.0 - x) * q * c * c / (2.0 * phi * phi.sqrt())
+            }
+            AsphereDefinition::XyPolynomial { .. } | AsphereDefinition::Zernike { .. } => 0.0,
+            // the r^1 term has a cusp at the vertex, calc_slope handles odd surfaces directly
+            AsphereDefinition::Odd => {
+                let r = r2.sqrt();
+                if r > 0.0 {
+                    odd_poly_slope(r, &self.coeffs) / (2.0 * r)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    // mutable access to coefficient idx, zero filling any missing lower order terms
+    pub fn coeff_mut(&mut self, idx: usize) -> &mut f64 {
+        if self.coeffs.len() <= idx {
+            self.coeffs.resize(idx + 1, 0.0);
+        }
+        &mut self.coeffs[idx]
+    }
+
     pub fn curv(&self) -> f64 {
         if self.r == 0. {
             0.
@@ -52,18 +266,201 @@ impl Side {
     }
 }
 
+// Decenter (dx, dy) and tilt (alpha, beta, gamma in radians) of a single surface. The surface
+// is decentered first, then tilted about x, the new y and the new z. Only the surface itself
+// moves, following surfaces stay on the axis.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoordBreak {
+    pub dx: f64,
+    pub dy: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+}
+
+impl CoordBreak {
+    pub fn is_identity(&self) -> bool {
+        *self == CoordBreak::default()
+    }
+
+    // global direction to the surface frame
+    pub fn dir_to_local(&self, v: &Vector3D) -> Vector3D {
+        v.rotate_x(-self.alpha)
+            .rotate_y(-self.beta)
+            .rotate_z(-self.gamma)
+    }
+
+    // surface frame direction to global
+    pub fn dir_to_global(&self, v: &Vector3D) -> Vector3D {
+        v.rotate_z(self.gamma)
+            .rotate_y(self.beta)
+            .rotate_x(self.alpha)
+    }
+
+    // global point to the frame of a surface whose vertex sits at zvertex
+    pub fn point_to_local(&self, p: &Vector3D, zvertex: f64) -> Vector3D {
+        self.dir_to_local(&Vector3D {
+            x: p.x - self.dx,
+            y: p.y - self.dy,
+            z: p.z - zvertex,
+        })
+    }
+
+    pub fn point_to_global(&self, p: &Vector3D, zvertex: f64) -> Vector3D {
+        let g = self.dir_to_global(p);
+        Vector3D {
+            x: g.x + self.dx,
+            y: g.y + self.dy,
+            z: g.z + zvertex,
+        }
+    }
+}
+
+// One surface of a sequential system. `thickness` is the axial distance from this surface's
+// vertex to the next one and `n_index` is the medium that follows the surface. A mirror
+// reflects instead of refracting; the light then travels toward -z, so the thickness after
+// an odd number of mirrors is negative. `n_index` is always the positive medium index.
+// `clear_ap` is this surface's clear aperture diameter, the lens clear aperture applies when
+// it is not set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Surface {
+    pub side: Side,
+    pub thickness: f64,
+    #[serde(default = "air_index")]
+    pub n_index: f64,
+    #[serde(default)]
+    pub coord_break: CoordBreak,
+    #[serde(default)]
+    pub mirror: bool,
+    #[serde(default)]
+    pub clear_ap: Option<f64>,
+    // catalog name of the medium after the surface, replaces n_index at a wavelength
+    #[serde(default)]
+    pub material: Option<String>,
+}
+
+fn air_index() -> f64 {
+    1.0
+}
+
+impl Surface {
+    pub fn new(side: Side, thickness: f64, n_index: f64) -> Surface {
+        Surface {
+            side,
+            thickness,
+            n_index,
+            coord_break: CoordBreak::default(),
+            mirror: false,
+            clear_ap: None,
+            material: None,
+        }
+    }
+}
+
+// Sequential optical system: an ordered list of surfaces starting at z = 0 in air.
+// Payloads may describe either the full surface list or the original single lens
+// (side1, side2, ct, n_index), which is expanded into a two surface system.
+// `stop` is the index of the aperture stop surface. A stop away from the lens surfaces is a
+// plane surface that keeps the surrounding index and carries its own clear aperture.
+// `intersect` tunes the ray/surface intersection solver.
+// Surfaces naming a material take their index from the catalog through `at_wavelength`.
+// `object_distance` runs from the object plane to the first vertex, None puts the object at
+// infinity and a negative distance is a virtual object behind the first vertex.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "LensPayload")]
 pub struct Lens {
     pub diameter: f64,
     pub clear_ap: f64,
-    pub ct: f64,
-    pub n_index: f64,
-    pub side1: Side,
-    pub side2: Side,
+    pub surfaces: Vec<Surface>,
+    pub stop: Option<usize>,
+    pub intersect: IntersectConfig,
+    pub object_distance: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LensPayload {
+    Sequential {
+        diameter: f64,
+        clear_ap: f64,
+        surfaces: Vec<Surface>,
+        #[serde(default)]
+        stop: Option<usize>,
+        #[serde(default)]
+        intersect: IntersectConfig,
+        #[serde(default)]
+        object_distance: Option<f64>,
+    },
+    Singlet {
+        diameter: f64,
+        clear_ap: f64,
+        ct: f64,
+        #[serde(default = "air_index")]
+        n_index: f64,
+        #[serde(default)]
+        material: Option<String>,
+        side1: Side,
+        side2: Side,
+        #[serde(default)]
+        intersect: IntersectConfig,
+        #[serde(default)]
+        object_distance: Option<f64>,
+    },
+}
+
+impl From<LensPayload> for Lens {
+    fn from(payload: LensPayload) -> Self {
+        match payload {
+            LensPayload::Sequential {
+                diameter,
+                clear_ap,
+                surfaces,
+                stop,
+                intersect,
+                object_distance,
+            } => Lens {
+                stop,
+                intersect,
+                object_distance,
+                ..Lens::new(diameter, clear_ap, surfaces)
+            },
+            LensPayload::Singlet {
+                diameter,
+                clear_ap,
+                ct,
+                n_index,
+                material,
+                side1,
+                side2,
+                intersect,
+                object_distance,
+            } => {
+                let mut lens = Lens {
+                    intersect,
+                    object_distance,
+                    ..Lens::singlet(diameter, clear_ap, ct, n_index, side1, side2)
+                };
+                lens.surfaces[0].material = material;
+                lens
+            }
+        }
+    }
 }
 
 impl Lens {
-    pub fn new(
+    pub fn new(diameter: f64, clear_ap: f64, surfaces: Vec<Surface>) -> Self {
+        Self {
+            diameter,
+            clear_ap,
+            surfaces,
+            stop: None,
+            intersect: IntersectConfig::default(),
+            object_distance: None,
+        }
+    }
+
+    pub fn singlet(
         diameter: f64,
         clear_ap: f64,
         ct: f64,
@@ -71,27 +468,334 @@ impl Lens {
         side1: Side,
         side2: Side,
     ) -> Self {
-        Self {
+        Self::new(
             diameter,
             clear_ap,
-            ct,
-            n_index,
-            side1,
-            side2,
+            vec![
+                Surface::new(side1, ct, n_index),
+                Surface::new(side2, 0.0, 1.0),
+            ],
+        )
+    }
+
+    // copy of the lens with the index after every surface that names a material evaluated at
+    // the wavelength in microns
+    pub fn at_wavelength(&self, wavelength: f64) -> Result<Lens, MaterialError> {
+        let mut lens = self.clone();
+        for surf in &mut lens.surfaces {
+            if let Some(name) = &surf.material {
+                surf.n_index = Material::from_name(name)?.n_index_at(wavelength)?;
+            }
+        }
+        Ok(lens)
+    }
+
+    // n(short) - n(long) of the medium after each surface, zero where no material is named
+    pub fn dispersion(&self, short: f64, long: f64) -> Result<Vec<f64>, MaterialError> {
+        self.surfaces
+            .iter()
+            .map(|surf| match &surf.material {
+                Some(name) => {
+                    let material = Material::from_name(name)?;
+                    Ok(material.n_index_at(short)? - material.n_index_at(long)?)
+                }
+                None => Ok(0.0),
+            })
+            .collect()
+    }
+
+    // z position of the last surface vertex
+    pub fn track(&self) -> f64 {
+        match self.surfaces.split_last() {
+            Some((_, rest)) => rest.iter().map(|s| s.thickness).sum(),
+            None => 0.0,
         }
     }
 
     pub fn efl(&self) -> f64 {
-        let phi = (self.n_index - 1.)
-            * (self.side1.curv() - self.side2.curv()
-                + ((self.n_index - 1.) * self.ct * self.side1.curv() * self.side2.curv())
-                    / self.n_index);
-        1. / phi
+        let (ray, _) = paraxial::trace(&self.surfaces, ParaxialRay::new(1., 0.), 1.);
+        -1. / ray.nu
+    }
+
+    // radius that vignettes rays on a surface, its own clear aperture when it has one and
+    // otherwise the lens clear aperture or diameter
+    pub fn clear_radius(&self, surface: usize) -> Option<f64> {
+        if let Some(clear_ap) = self.surfaces.get(surface).and_then(|s| s.clear_ap) {
+            Some(clear_ap / 2.0)
+        } else if self.clear_ap > 0.0 {
+            Some(self.clear_ap / 2.0)
+        } else if self.diameter > 0.0 {
+            Some(self.diameter / 2.0)
+        } else {
+            None
+        }
     }
 
+    // signed along z, negative when the image space travels toward -z
     pub fn bfl(&self) -> f64 {
-        let efl = self.efl();
-        let pp = (self.n_index - 1.) * self.side1.curv() * efl * (1. / self.n_index) * self.ct;
-        efl - pp
+        let (ray, n) = paraxial::trace(&self.surfaces, ParaxialRay::new(1., 0.), 1.);
+        ray.axis_crossing(n)
+    }
+
+    // signed distance from the last vertex to the paraxial image of the object, the bfl for an
+    // object at infinity
+    pub fn image_distance(&self) -> f64 {
+        match self.object_distance {
+            None => self.bfl(),
+            Some(d) => {
+                // axial ray leaving the object at unit slope
+                let (ray, n) = paraxial::trace(&self.surfaces, ParaxialRay::new(d, 1.), 1.);
+                ray.axis_crossing(n)
+            }
+        }
+    }
+
+    // paraxial lateral magnification of the object, zero for an object at infinity
+    pub fn magnification(&self) -> f64 {
+        match self.object_distance {
+            None => 0.,
+            Some(d) => {
+                let (ray, _) = paraxial::trace(&self.surfaces, ParaxialRay::new(d, 1.), 1.);
+                1. / ray.nu
+            }
+        }
+    }
+
+    // z position of the object plane, None for an object at infinity
+    pub fn object_plane(&self) -> Option<f64> {
+        self.object_distance.map(|d| -d)
+    }
+
+    // image plane z position, refocus is measured along the direction of travel
+    pub fn image_plane(&self, refocus: f64) -> f64 {
+        self.track() + self.image_distance() + self.n_image().signum() * refocus
+    }
+
+    // refocus that puts the image plane at z
+    pub fn refocus_to(&self, z: f64) -> f64 {
+        (z - self.track() - self.image_distance()) * self.n_image().signum()
+    }
+
+    // z position of the paraxial exit pupil, the image of the stop (the first surface when no
+    // stop is set) in image space. Infinite for an image space telecentric system.
+    pub fn exit_pupil(&self) -> f64 {
+        paraxial::exit_pupil(self)
+    }
+
+    // signed index of the image space, negative after an odd number of mirrors
+    fn n_image(&self) -> f64 {
+        paraxial::index_after(&self.surfaces, 1.)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // uncorrected f = 49 BK7 singlet shared by the chromatic and image quality tests
+    pub(crate) fn bk7_singlet() -> Lens {
+        let mut lens = Lens::singlet(
+            25.,
+            24.,
+            5.,
+            1.5,
+            Side::new(50., 0., vec![]),
+            Side::new(-50., 0., vec![]),
+        );
+        lens.surfaces[0].material = Some("Bk7".to_string());
+        lens
+    }
+
+    fn thick_lens_efl(r1: f64, r2: f64, ct: f64, n: f64) -> f64 {
+        let (c1, c2) = (1. / r1, 1. / r2);
+        1. / ((n - 1.) * (c1 - c2 + (n - 1.) * ct * c1 * c2 / n))
+    }
+
+    #[test]
+    fn singlet_matches_thick_lens() {
+        let lens = Lens::singlet(
+            25.,
+            24.,
+            6.,
+            1.5,
+            Side::new(50., 0., vec![]),
+            Side::new(-80., 0., vec![]),
+        );
+        let efl = thick_lens_efl(50., -80., 6., 1.5);
+        let bfl = efl - 0.5 * (1. / 50.) * efl * 6. / 1.5;
+
+        assert!((lens.efl() - efl).abs() < 1e-9);
+        assert!((lens.bfl() - bfl).abs() < 1e-9);
+        assert_eq!(lens.track(), 6.);
+    }
+
+    #[test]
+    fn singlet_payload_deserializes() {
+        let lens: Lens = serde_json::from_str(
+            r#"{"diameter": 25, "clear_ap": 24, "ct": 6, "n_index": 1.5,
+                "side1": {"r": 50, "k": 0, "ad": 0, "ae": 0},
+                "side2": {"r": -80, "k": 0, "ad": 0, "ae": 0}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(lens.surfaces.len(), 2);
+        assert_eq!(lens.surfaces[0].thickness, 6.);
+        assert_eq!(lens.surfaces[0].n_index, 1.5);
+        assert_eq!(lens.surfaces[1].n_index, 1.);
+        assert_eq!(lens.surfaces[0].side.coeffs, vec![0., 0.]);
+    }
+
+    #[test]
+    fn poly_slope_matches_sag() {
+        let side = Side::new(20., -1., vec![1e-5, -2e-8, 3e-11, -4e-14]);
+        let (r2, h) = (16., 1e-4);
+        let numeric = (side.poly_sag(r2 + h) - side.poly_sag(r2 - h)) / (2. * h);
+
+        assert!((side.poly_slope(r2) - numeric).abs() < 1e-9);
+        assert!(matches!(side.surf_type(), SurfaceType::Asphere));
+    }
+
+    #[test]
+    fn conic_sag_ends_at_the_conic_edge() {
+        let side = Side::new(10., 0., vec![]);
+        assert!((side.conic_sag(64.).unwrap() - 4.).abs() < 1e-12);
+        assert_eq!(side.conic_sag(101.), None);
+        assert_eq!(Side::new(0., 0., vec![]).conic_sag(1e6), Some(0.));
+    }
+
+    #[test]
+    fn qbfs_poly_slope_matches_sag() {
+        let mut side = Side::new(20., 0., vec![1e-3, -2e-4, 5e-5]);
+        side.definition = AsphereDefinition::Qbfs { norm_radius: 6. };
+        let (r2, h) = (16., 1e-4);
+        let numeric = (side.poly_sag(r2 + h) - side.poly_sag(r2 - h)) / (2. * h);
+
+        assert!((side.poly_slope(r2) - numeric).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thin_doublet_power_adds() {
+        // two thin lenses in contact, each 100 mm focal length
+        let side = |r: f64| Side::new(r, 0., vec![]);
+        let lens = Lens::new(
+            25.,
+            24.,
+            vec![
+                Surface::new(side(100.), 0., 1.5),
+                Surface::new(side(-100.), 0., 1.),
+                Surface::new(side(100.), 0., 1.5),
+                Surface::new(side(-100.), 0., 1.),
+            ],
+        );
+
+        assert!((lens.efl() - 50.).abs() < 1e-9);
+        assert!((lens.bfl() - 50.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn surface_apertures_deserialize() {
+        let lens: Lens = serde_json::from_value(serde_json::json!({
+            "diameter": 25.0,
+            "clear_ap": 24.0,
+            "stop": 0,
+            "surfaces": [
+                {"side": {"r": 0.0, "k": 0.0, "coeffs": []}, "thickness": 2.0, "n_index": 1.0,
+                 "clear_ap": 10.0},
+                {"side": {"r": 50.0, "k": 0.0, "coeffs": []}, "thickness": 5.0, "n_index": 1.5},
+                {"side": {"r": -50.0, "k": 0.0, "coeffs": []}, "thickness": 0.0, "n_index": 1.0}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(lens.stop, Some(0));
+        assert_eq!(lens.clear_radius(0), Some(5.0));
+        assert_eq!(lens.clear_radius(1), Some(12.0));
+    }
+
+    #[test]
+    fn materials_resolve_at_wavelength() {
+        let lens: Lens = serde_json::from_value(serde_json::json!({
+            "diameter": 25.0,
+            "clear_ap": 24.0,
+            "ct": 5.0,
+            "material": "Bk7",
+            "side1": {"r": 50.0, "k": 0.0, "coeffs": []},
+            "side2": {"r": -50.0, "k": 0.0, "coeffs": []}
+        }))
+        .unwrap();
+
+        let blue = lens.at_wavelength(0.4861).unwrap();
+        let red = lens.at_wavelength(0.6563).unwrap();
+        assert!(blue.surfaces[0].n_index > red.surfaces[0].n_index);
+        assert_eq!(blue.surfaces[1].n_index, 1.0);
+        assert!(blue.efl() < red.efl());
+
+        let dispersion = lens.dispersion(0.4861, 0.6563).unwrap();
+        assert!(
+            (dispersion[0] - (blue.surfaces[0].n_index - red.surfaces[0].n_index)).abs() < 1e-15
+        );
+        assert_eq!(dispersion[1], 0.0);
+
+        assert!(matches!(
+            lens.at_wavelength(10.6),
+            Err(MaterialError::OutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn exit_pupil_images_stop() {
+        // stop in front of a thin lens, the exit pupil is its virtual image
+        let mut stop = Surface::new(Side::new(0., 0., vec![]), 25., 1.);
+        stop.clear_ap = Some(10.);
+        let mut lens = Lens::new(
+            25.,
+            24.,
+            vec![
+                stop,
+                Surface::new(Side::new(50., 0., vec![]), 0., 2.),
+                Surface::new(Side::new(-50., 0., vec![]), 0., 1.),
+            ],
+        );
+        lens.stop = Some(0);
+
+        // f = 25, object at 25 images to infinity
+        assert!(lens.exit_pupil().abs() > 1e9);
+        lens.surfaces[0].thickness = 12.5;
+        assert!((lens.exit_pupil() - (12.5 - 25.)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn finite_conjugates() {
+        // thin f = 50 lens
+        let mut lens = Lens::singlet(
+            25.,
+            24.,
+            0.,
+            1.5,
+            Side::new(50., 0., vec![]),
+            Side::new(-50., 0., vec![]),
+        );
+        lens.object_distance = Some(100.);
+        assert!((lens.image_distance() - 100.).abs() < 1e-9);
+        assert!((lens.magnification() + 1.).abs() < 1e-9);
+        assert_eq!(lens.object_plane(), Some(-100.));
+
+        // converging beam toward a virtual object 100 behind the lens
+        lens.object_distance = Some(-100.);
+        assert!((lens.image_distance() - 100. / 3.).abs() < 1e-9);
+
+        lens.object_distance = None;
+        assert!((lens.image_distance() - lens.bfl()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn concave_mirror_focus() {
+        let mut mirror = Surface::new(Side::new(-100., 0., vec![]), 0., 1.);
+        mirror.mirror = true;
+        let lens = Lens::new(25., 24., vec![mirror]);
+
+        assert!((lens.efl() - 50.).abs() < 1e-9);
+        assert!((lens.bfl() + 50.).abs() < 1e-9);
+        assert!((lens.image_plane(1.) + 51.).abs() < 1e-9);
     }
 }
@@ -1,29 +1,48 @@
 #[macro_use]
 extern crate impl_ops;
 
+mod agf;
 mod fermi;
 mod fft;
 mod lens;
+mod material;
 mod optimize;
+mod paraxial;
+mod qpoly;
 mod raytrace;
+mod seidel;
 mod utils;
+mod zmx;
 
+use agf::parse_agf;
 use fermi::fittofermi_dirac;
 use fft::{
     _rustfftmidline, rustfft,
     utils::{gen_zero_2d, get_complex_vec, slicecore},
 };
 use lens::{Lens, Side, SurfaceType};
+use material::Material;
+use paraxial::first_order;
+use qpoly::{to_qbfs, to_qcon, to_standard};
 use raytrace::{
-    gen_random_rays,
-    ray_vector::{Vector3D, CPROPV},
-    trace_ray,
-    wfe::calc_opd_slim,
+    chromatic::{common_refocus, focal_shift, lateral_color, lenses_at, normalized_weights},
+    fans::{axial_aberrations, field_fans, AxialAberrations, FieldFans},
+    field::{chief_ray, marginal_ray, orient_to_chief, pupil_ray, Field},
+    gen_object_rays, gen_random_rays,
+    ray_vector::{Ray, Vector3D, CPROPV},
+    sampling::{DiscSampler, RaySampling},
+    source_rng,
+    spot::{field_spot, FieldSpot},
+    trace_ray, trace_ray_path,
+    wfe::{calc_opd_opl, calc_opd_slim, OpdReference},
 };
+use seidel::seidel;
+use serde::Serialize;
 use std::f64::consts::PI;
 use std::f64::consts::SQRT_2;
 use utils::set_panic_hook;
 use wasm_bindgen::prelude::*;
+use zmx::{parse_zmx, write_zmx, ZmxSystem, ZmxWarning};
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global allocator.
 #[cfg(feature = "wee_alloc")]
@@ -40,6 +59,8 @@ extern "C" {
 pub struct TraceResults {
     p_vectors: Vec<f64>,
     e_vectors: Vec<f64>,
+    wavelengths: Vec<f64>,
+    num_failed: usize,
 }
 
 #[wasm_bindgen]
@@ -49,6 +70,8 @@ impl TraceResults {
         TraceResults {
             p_vectors: vec![],
             e_vectors: vec![],
+            wavelengths: vec![],
+            num_failed: 0,
         }
     }
 
@@ -71,8 +94,65 @@ impl TraceResults {
     pub fn e_size(&self) -> usize {
         self.e_vectors.len()
     }
+
+    // wavelength of each output ray
+    #[wasm_bindgen(getter, js_name = "wPtr")]
+    pub fn w_ptr(&self) -> *const f64 {
+        self.wavelengths.as_ptr()
+    }
+
+    #[wasm_bindgen(getter, js_name = "wSize")]
+    pub fn w_size(&self) -> usize {
+        self.wavelengths.len()
+    }
+
+    // rays that were vignetted or failed, they are left out of the vectors
+    #[wasm_bindgen(getter, js_name = "numFailed")]
+    pub fn num_failed(&self) -> usize {
+        self.num_failed
+    }
 }
 
+// Extended source rays for the on axis field. An object at infinity is a fiber of radius
+// fiber_radius at the focus of the lens, seen as a cone of angles. A finite object is the fiber
+// itself on the object plane. sampling picks the patterns across the pupil and the source. The
+// same seed gives the same rays, None draws a new one.
+fn gen_source_rays(
+    num_rays: usize,
+    num_angles: usize,
+    fiber_radius: f64,
+    source_radius: f64,
+    lens: &Lens,
+    sampling: &RaySampling,
+    seed: Option<u32>,
+) -> Vec<Ray> {
+    let mut rng = source_rng(seed.map(u64::from));
+    match lens.object_plane() {
+        None => {
+            let half_ang = fiber_radius / lens.efl();
+            gen_random_rays(
+                num_rays,
+                num_angles,
+                source_radius,
+                half_ang,
+                sampling,
+                &mut rng,
+            )
+        }
+        Some(zobject) => gen_object_rays(
+            num_rays,
+            num_angles,
+            source_radius,
+            fiber_radius,
+            zobject,
+            sampling,
+            &mut rng,
+        ),
+    }
+}
+
+// The same source rays are launched at every wavelength, aimed with the chief ray of the first
+// one, and traced to the image plane of the first wavelength.
 #[wasm_bindgen(js_name = "runWASMRaytrace")]
 pub fn run_raytrace(
     num_rays: usize,
@@ -80,49 +160,210 @@ pub fn run_raytrace(
     fiber_radius: f64,
     source_radius: f64,
     refocus: f64,
+    wavelengths: &[f64],
     lens_payload: &JsValue,
-) -> TraceResults {
+    field_payload: &JsValue,
+    sampling_payload: &JsValue,
+    seed: Option<u32>,
+) -> Option<TraceResults> {
     set_panic_hook();
 
-    let lens: Lens = lens_payload.into_serde().unwrap();
-    //log(&format!("rusty {:?}", lens));
-    // log(&format!("rusty {:?}", lens));
-    //log(&format!("rusty {:?}", source_radius));
+    let lenses = lenses_for(lens_payload, wavelengths)?;
+    let refocus = common_refocus(&lenses, refocus);
+
+    let in_rays = gen_source_rays(
+        num_rays,
+        num_angles,
+        fiber_radius,
+        source_radius,
+        &lenses[0],
+        &sampling_from(sampling_payload),
+        seed,
+    );
+    let chief = field_chief(field_payload, &lenses[0]);
+
+    let num_out = in_rays.len() * lenses.len();
+    let mut p_vecs = Vec::with_capacity(num_out * 3);
+    let mut e_vecs = Vec::with_capacity(num_out * 3);
+    let mut w_vecs = Vec::with_capacity(num_out);
+    let mut num_failed = 0;
+
+    for ((lens, refocus), wavelength) in lenses.iter().zip(refocus).zip(wavelengths) {
+        for in_r in &in_rays {
+            let traced = chief
+                .as_ref()
+                .map(|chief| trace_ray(&orient_to_chief(in_r, chief, lens), lens, refocus));
+            let Some(Ok(out_r)) = traced else {
+                num_failed += 1;
+                continue;
+            };
+            p_vecs.push(out_r.pvector.x);
+            p_vecs.push(out_r.pvector.y);
+            p_vecs.push(out_r.pvector.z);
+
+            e_vecs.push(out_r.edir.x);
+            e_vecs.push(out_r.edir.y);
+            e_vecs.push(out_r.edir.z);
+
+            w_vecs.push(*wavelength);
+        }
+    }
+    Some(TraceResults {
+        p_vectors: p_vecs,
+        e_vectors: e_vecs,
+        wavelengths: w_vecs,
+        num_failed,
+    })
+}
 
-    let half_ang = fiber_radius / lens.efl();
+// Surface by surface paths of a ray bundle for the 3D views. Every ray takes stride vertices:
+// its launch point, its intercept with each surface and its image point. points holds x, y, z
+// and directions the direction leaving each vertex. A ray that fails stops where it failed, its
+// remaining vertices are NaN and lengths holds the number of vertices it reached.
+#[wasm_bindgen]
+pub struct RayPaths {
+    points: Vec<f64>,
+    directions: Vec<f64>,
+    lengths: Vec<u32>,
+    stride: usize,
+}
 
-    let in_rays = gen_random_rays(num_rays, num_angles, source_radius, half_ang);
-    // let out_rays = Vec::with_capacity(num_rays * num_angles);
+impl RayPaths {
+    fn trace(rays: &[Ray], lens: &Lens, refocus: f64) -> RayPaths {
+        let stride = lens.surfaces.len() + 2;
+        let mut points = Vec::with_capacity(rays.len() * stride * 3);
+        let mut directions = Vec::with_capacity(rays.len() * stride * 3);
+        let mut lengths = Vec::with_capacity(rays.len());
+
+        for ray in rays {
+            let (path, _) = trace_ray_path(ray, lens, refocus);
+            for vertex in &path {
+                let (p, e) = (&vertex.pvector, &vertex.edir);
+                points.extend([p.x, p.y, p.z]);
+                directions.extend([e.x, e.y, e.z]);
+            }
+            let missing = 3 * (stride - path.len());
+            points.resize(points.len() + missing, f64::NAN);
+            directions.resize(directions.len() + missing, f64::NAN);
+            lengths.push(path.len() as u32);
+        }
+        RayPaths {
+            points,
+            directions,
+            lengths,
+            stride,
+        }
+    }
+}
 
-    let mut p_vecs = Vec::with_capacity(in_rays.len() * 3);
-    let mut e_vecs = Vec::with_capacity(in_rays.len() * 3);
+#[wasm_bindgen]
+impl RayPaths {
+    #[wasm_bindgen(getter, js_name = "pointsPtr")]
+    pub fn points_ptr(&self) -> *const f64 {
+        self.points.as_ptr()
+    }
 
-    for in_r in &in_rays {
-        let out_r = trace_ray(&in_r, &lens, refocus);
-        p_vecs.push(out_r.pvector.x);
-        p_vecs.push(out_r.pvector.y);
-        p_vecs.push(out_r.pvector.z);
+    #[wasm_bindgen(getter, js_name = "pointsSize")]
+    pub fn points_size(&self) -> usize {
+        self.points.len()
+    }
 
-        e_vecs.push(out_r.edir.x);
-        e_vecs.push(out_r.edir.y);
-        e_vecs.push(out_r.edir.z);
+    #[wasm_bindgen(getter, js_name = "directionsPtr")]
+    pub fn directions_ptr(&self) -> *const f64 {
+        self.directions.as_ptr()
     }
-    TraceResults {
-        p_vectors: p_vecs,
-        e_vectors: e_vecs,
+
+    #[wasm_bindgen(getter, js_name = "directionsSize")]
+    pub fn directions_size(&self) -> usize {
+        self.directions.len()
     }
+
+    #[wasm_bindgen(getter, js_name = "lengthsPtr")]
+    pub fn lengths_ptr(&self) -> *const u32 {
+        self.lengths.as_ptr()
+    }
+
+    #[wasm_bindgen(getter, js_name = "lengthsSize")]
+    pub fn lengths_size(&self) -> usize {
+        self.lengths.len()
+    }
+
+    // vertices per ray
+    #[wasm_bindgen(getter)]
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+}
+
+// paths of launch rays given as px, py, pz, ex, ey, ez each
+#[wasm_bindgen(js_name = "traceRayPaths")]
+pub fn trace_ray_paths(
+    rays: &[f64],
+    refocus: f64,
+    wavelength: f64,
+    lens_payload: &JsValue,
+) -> Option<RayPaths> {
+    set_panic_hook();
+    let lens = lens_at(lens_payload, wavelength)?;
+    let rays = rays
+        .chunks_exact(6)
+        .map(|r| Ray {
+            pvector: Vector3D {
+                x: r[0],
+                y: r[1],
+                z: r[2],
+            },
+            edir: Vector3D {
+                x: r[3],
+                y: r[4],
+                z: r[5],
+            },
+        })
+        .collect::<Vec<Ray>>();
+    Some(RayPaths::trace(&rays, &lens, refocus))
+}
+
+// paths of num_rays rays of the field spread over a pupil of source_radius on the z = 0 plane,
+// centered on the chief ray
+#[wasm_bindgen(js_name = "traceBundlePaths")]
+pub fn trace_bundle_paths(
+    num_rays: usize,
+    source_radius: f64,
+    refocus: f64,
+    wavelength: f64,
+    lens_payload: &JsValue,
+    field_payload: &JsValue,
+    sampling_payload: &JsValue,
+    seed: Option<u32>,
+) -> Option<RayPaths> {
+    set_panic_hook();
+    let lens = lens_at(lens_payload, wavelength)?;
+    let chief = field_chief(field_payload, &lens)?;
+    let sampling = sampling_from(sampling_payload);
+    let mut rng = source_rng(seed.map(u64::from));
+
+    let rays = DiscSampler::new(sampling.pupil)
+        .points(num_rays, source_radius, &mut rng)
+        .into_iter()
+        .map(|(x, y)| pupil_ray(&chief, &lens, x, y))
+        .collect::<Vec<Ray>>();
+    Some(RayPaths::trace(&rays, &lens, refocus))
 }
 
 #[wasm_bindgen]
 pub struct PSFResult {
     data: Vec<f64>,
+    num_failed: usize,
 }
 
 #[wasm_bindgen]
 impl PSFResult {
     #[wasm_bindgen(constructor)]
     pub fn new() -> PSFResult {
-        PSFResult { data: vec![] }
+        PSFResult {
+            data: vec![],
+            num_failed: 0,
+        }
     }
 
     #[wasm_bindgen(getter, js_name = "dataPtr")]
@@ -134,157 +375,666 @@ impl PSFResult {
     pub fn data_size(&self) -> usize {
         self.data.len()
     }
+
+    // pupil samples whose ray was vignetted or failed, they are masked out of the pupil
+    #[wasm_bindgen(getter, js_name = "numFailed")]
+    pub fn num_failed(&self) -> usize {
+        self.num_failed
+    }
 }
 
-#[wasm_bindgen(js_name = "genPSF")]
-pub fn genpsf(
-    loopsize: usize,
-    totalsize: usize,
-    psfgridsize: usize,
-    wavelength: f64,
-    source_radius: f64,
-    refocus: f64,
-    lens_payload: &JsValue,
-) -> PSFResult {
-    set_panic_hook();
-    let lens: Lens = lens_payload.into_serde().unwrap();
-    let mut amp = gen_zero_2d(loopsize);
-    let mut mask = gen_zero_2d(loopsize);
+// How the pupil OPD is computed for the PSF generators. Lsa integrates a fit to the
+// longitudinal spherical aberration and only holds for rotationally symmetric systems,
+// OpticalPath traces n * L to a reference sphere at the exit pupil.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpdMode {
+    Lsa = 0,
+    OpticalPath = 1,
+}
 
-    let mut x: f64;
-    let mut y: f64;
-    let diag = source_radius * source_radius;
+// lens payload with its materials evaluated at the wavelength in microns, None (and a console
+// message) when a material is unknown or does not cover the wavelength
+fn lens_at(lens_payload: &JsValue, wavelength: f64) -> Option<Lens> {
+    let lens: Lens = lens_payload.into_serde().unwrap();
+    match lens.at_wavelength(wavelength) {
+        Ok(lens) => Some(lens),
+        Err(err) => {
+            log(&format!("lens not valid at {} um: {:?}", wavelength, err));
+            None
+        }
+    }
+}
 
-    let step = 2.0 * source_radius / (loopsize - 1) as f64;
-
-    for row in 0..psfgridsize {
-        y = source_radius - row as f64 * step;
-        for col in 0..psfgridsize {
-            x = -source_radius + col as f64 * step;
-            if (x * x + y * y) < diag {
-                let p0 = Vector3D { x, y, z: 0.0 };
-                amp[row][col] = 2.0 * PI * calc_opd_slim(p0, CPROPV, &lens, wavelength, refocus); // multiply by 2pi to scale for fft
-                mask[row][col] = 1.0;
-            } else {
-                amp[row][col] = 0.0;
-                mask[row][col] = 0.0;
-            }
+// lens payload evaluated at each wavelength of a spectrum, None (and a console message) as in
+// lens_at
+fn lenses_for(lens_payload: &JsValue, wavelengths: &[f64]) -> Option<Vec<Lens>> {
+    let lens: Lens = lens_payload.into_serde().unwrap();
+    match lenses_at(&lens, wavelengths) {
+        Ok(lenses) => Some(lenses),
+        Err(err) => {
+            log(&format!(
+                "lens not valid at {:?} um: {:?}",
+                wavelengths, err
+            ));
+            None
         }
     }
+}
+
+// field from an optional payload, on axis when none is passed
+fn field_from(field_payload: &JsValue) -> Field {
+    if field_payload.is_undefined() || field_payload.is_null() {
+        Field::default()
+    } else {
+        field_payload.into_serde().unwrap()
+    }
+}
+
+// list of fields from an optional payload, the on axis field when none is passed
+fn fields_from(fields_payload: &JsValue) -> Vec<Field> {
+    if fields_payload.is_undefined() || fields_payload.is_null() {
+        vec![Field::default()]
+    } else {
+        fields_payload.into_serde().unwrap()
+    }
+}
 
-    let mut data = get_complex_vec(&amp, &mask, totalsize);
-    let mut datadl = get_complex_vec(&mask, &mask, totalsize);
-    let datafull = rustfft(&mut data, &mut datadl);
-    let _datatoc = slicecore(datafull, psfgridsize);
-    //console.log(_datatoc);
-    //console.log("lib.rs:  ***************");
-    let mut psfdata: Vec<f64> = vec![];
+// sampling from an optional payload, random across the pupil and the source when none is passed
+fn sampling_from(sampling_payload: &JsValue) -> RaySampling {
+    if sampling_payload.is_undefined() || sampling_payload.is_null() {
+        RaySampling::default()
+    } else {
+        sampling_payload.into_serde().unwrap()
+    }
+}
 
-    for row in 0..psfgridsize {
-        for col in 0..psfgridsize {
-            psfdata.push(_datatoc[row][col]);
+// chief ray of the field payload, None (and a console message) when it cannot be aimed
+fn field_chief(field_payload: &JsValue, lens: &Lens) -> Option<Ray> {
+    let field = field_from(field_payload);
+    match chief_ray(&field, lens) {
+        Ok(chief) => Some(chief),
+        Err(err) => {
+            log(&format!("no chief ray for {:?}: {:?}", field, err));
+            None
         }
     }
-    PSFResult { data: psfdata }
 }
 
-#[wasm_bindgen(js_name = "genPSFLine")]
-pub fn genpsfline(
-    gridsize: usize,
-    totalsize: usize,
+// OPD in waves at a pupil point relative to where the chief ray crosses z = 0, None when the
+// ray fails. The LSA fit only describes the on axis collimated beam, other fields and finite
+// objects always use the optical path.
+fn opd_sampler<'a>(
+    lens: &'a Lens,
     wavelength: f64,
-    source_radius: f64,
     refocus: f64,
-    lens_payload: &JsValue,
-) -> PSFResult {
-    set_panic_hook();
-    let lens: Lens = lens_payload.into_serde().unwrap();
-    //log(&format!("rusty {:?}", lens));
+    opd_mode: OpdMode,
+    chief: Option<Ray>,
+) -> impl Fn(Vector3D) -> Option<f64> + 'a {
+    let on_axis = lens.object_plane().is_none() && chief.as_ref().is_some_and(|c| c.edir == CPROPV);
+    let opd_mode = if on_axis {
+        opd_mode
+    } else {
+        OpdMode::OpticalPath
+    };
+    let reference = match opd_mode {
+        OpdMode::OpticalPath => chief
+            .as_ref()
+            .and_then(|c| OpdReference::new(c, lens, refocus).ok()),
+        OpdMode::Lsa => None,
+    };
 
+    move |p0| {
+        let ray = pupil_ray(chief.as_ref()?, lens, p0.x, p0.y);
+        match opd_mode {
+            OpdMode::Lsa => calc_opd_slim(ray.pvector, CPROPV, lens, wavelength, refocus).ok(),
+            OpdMode::OpticalPath => reference.as_ref().and_then(|r| {
+                calc_opd_opl(ray.pvector, ray.edir, lens, wavelength, refocus, r).ok()
+            }),
+        }
+    }
+}
+
+// Pupil phase (2 pi OPD) and amplitude on a gridsize square grid across the source diameter,
+// with the number of samples inside the source whose ray failed. The amplitude is apodize(r^2)
+// and zero outside the source or where the ray fails.
+fn sample_pupil(
+    gridsize: usize,
+    source_radius: f64,
+    opd_at: impl Fn(Vector3D) -> Option<f64>,
+    apodize: impl Fn(f64) -> f64,
+) -> (Vec<Vec<f64>>, Vec<Vec<f64>>, usize) {
     let mut amp = gen_zero_2d(gridsize);
     let mut mask = gen_zero_2d(gridsize);
-    let zero = gen_zero_2d(gridsize);
-
-    let mut x: f64;
-    let mut y: f64;
     let diag = source_radius * source_radius;
-
     let step = 2.0 * source_radius / (gridsize - 1) as f64;
+    let mut num_failed = 0;
 
     for row in 0..gridsize {
-        y = source_radius - row as f64 * step;
+        let y = source_radius - row as f64 * step;
         for col in 0..gridsize {
-            x = -source_radius + col as f64 * step;
-            if (x * x + y * y) < diag {
-                let p0 = Vector3D { x, y, z: 0.0 };
-                amp[row][col] = 2.0 * PI * calc_opd_slim(p0, CPROPV, &lens, wavelength, refocus); // multiply by 2pi to scale for fft
-                mask[row][col] = 1.0;
-            } else {
-                amp[row][col] = 0.0;
-                mask[row][col] = 0.0;
+            let x = -source_radius + col as f64 * step;
+            let r2 = x * x + y * y;
+            if r2 >= diag {
+                continue;
             }
+            match opd_at(Vector3D { x, y, z: 0.0 }) {
+                Some(opd) => {
+                    amp[row][col] = 2.0 * PI * opd; // multiply by 2pi to scale for fft
+                    mask[row][col] = apodize(r2);
+                }
+                None => num_failed += 1,
+            }
+        }
+    }
+    (amp, mask, num_failed)
+}
+
+// Pupil grid of a wavelength. The FFT samples the image at wavelength / (pupil width), so
+// shrinking the grid by wavelengths[0] / wavelength puts every PSF on the image sampling of the
+// first wavelength.
+fn scaled_gridsize(gridsize: usize, scale: f64, totalsize: usize) -> usize {
+    ((gridsize as f64 * scale).round() as usize).clamp(2, totalsize)
+}
+
+// Each PSF comes normalized to its own diffraction limited peak, which grows with the square of
+// the summed pupil amplitude. This factor weights it back by its energy so a perfect lens still
+// peaks at one when the spectrum is summed.
+fn pupil_energy(mask: &[Vec<f64>]) -> f64 {
+    let sum: f64 = mask.iter().flatten().sum();
+    let sum2: f64 = mask.iter().flatten().map(|a| a * a).sum();
+    if sum2 > 0.0 {
+        sum * sum / sum2
+    } else {
+        0.0
+    }
+}
+
+// Weighted intensity sum of the PSF of each wavelength, all referred to the image plane of the
+// first wavelength. psf gets the OPD sampler of a wavelength and the pupil scale
+// wavelengths[0] / wavelength, and returns the PSF, its pupil_energy and failed samples.
+fn polychromatic_psf(
+    lens_payload: &JsValue,
+    field_payload: &JsValue,
+    wavelengths: &[f64],
+    weights: &[f64],
+    refocus: f64,
+    opd_mode: OpdMode,
+    psf: impl Fn(&dyn Fn(Vector3D) -> Option<f64>, f64) -> (Vec<f64>, f64, usize),
+) -> Option<PSFResult> {
+    let lenses = lenses_for(lens_payload, wavelengths)?;
+    let refocus = common_refocus(&lenses, refocus);
+    let weights = normalized_weights(wavelengths.len(), weights);
+
+    let mut data: Vec<f64> = vec![];
+    let mut total = 0.0;
+    let mut num_failed = 0;
+    for (((lens, refocus), wavelength), weight) in
+        lenses.iter().zip(refocus).zip(wavelengths).zip(weights)
+    {
+        let chief = field_chief(field_payload, lens);
+        let opd_at = opd_sampler(lens, *wavelength, refocus, opd_mode, chief);
+        let (psf_w, energy, failed) = psf(&opd_at, wavelengths[0] / wavelength);
+        num_failed += failed;
+        if data.is_empty() {
+            data = vec![0.0; psf_w.len()];
         }
+        // a pupil with no rays through has no diffraction limited peak to normalize by
+        if energy > 0.0 {
+            data.iter_mut()
+                .zip(psf_w)
+                .for_each(|(d, p)| *d += weight * energy * p);
+            total += weight * energy;
+        }
+    }
+    if total > 0.0 {
+        data.iter_mut().for_each(|d| *d /= total);
     }
+    Some(PSFResult { data, num_failed })
+}
 
-    let mut data = get_complex_vec(&amp, &mask, totalsize);
-    let mut datadl = get_complex_vec(&zero, &mask, totalsize);
-    let dataout = _rustfftmidline(&mut data, &mut datadl, gridsize);
+#[wasm_bindgen(js_name = "genPSF")]
+pub fn genpsf(
+    loopsize: usize,
+    totalsize: usize,
+    psfgridsize: usize,
+    wavelengths: &[f64],
+    weights: &[f64],
+    source_radius: f64,
+    refocus: f64,
+    opd_mode: OpdMode,
+    lens_payload: &JsValue,
+    field_payload: &JsValue,
+) -> Option<PSFResult> {
+    set_panic_hook();
+    polychromatic_psf(
+        lens_payload,
+        field_payload,
+        wavelengths,
+        weights,
+        refocus,
+        opd_mode,
+        |opd_at, scale| {
+            let gridsize = scaled_gridsize(loopsize, scale, totalsize);
+            let (amp, mask, num_failed) = sample_pupil(gridsize, source_radius, opd_at, |_| 1.0);
+
+            let mut data = get_complex_vec(&amp, &mask, totalsize);
+            let mut datadl = get_complex_vec(&mask, &mask, totalsize);
+            let datafull = rustfft(&mut data, &mut datadl);
+            let psf = slicecore(datafull, psfgridsize).concat();
+            (psf, pupil_energy(&mask), num_failed)
+        },
+    )
+}
 
-    PSFResult { data: dataout }
+#[wasm_bindgen(js_name = "genPSFLine")]
+pub fn genpsfline(
+    gridsize: usize,
+    totalsize: usize,
+    wavelengths: &[f64],
+    weights: &[f64],
+    source_radius: f64,
+    refocus: f64,
+    opd_mode: OpdMode,
+    lens_payload: &JsValue,
+    field_payload: &JsValue,
+) -> Option<PSFResult> {
+    set_panic_hook();
+    polychromatic_psf(
+        lens_payload,
+        field_payload,
+        wavelengths,
+        weights,
+        refocus,
+        opd_mode,
+        |opd_at, scale| {
+            let pupilsize = scaled_gridsize(gridsize, scale, totalsize);
+            let (amp, mask, num_failed) = sample_pupil(pupilsize, source_radius, opd_at, |_| 1.0);
+            let zero = gen_zero_2d(pupilsize);
+
+            let mut data = get_complex_vec(&amp, &mask, totalsize);
+            let mut datadl = get_complex_vec(&zero, &mask, totalsize);
+            let line = _rustfftmidline(&mut data, &mut datadl, gridsize);
+            (line, pupil_energy(&mask), num_failed)
+        },
+    )
 }
 
 #[wasm_bindgen(js_name = "genGaussLine")]
 pub fn gengaussline(
     gridsize: usize,
     totalsize: usize,
-    wavelength: f64,
+    wavelengths: &[f64],
+    weights: &[f64],
     source_radius: f64,
     source_e2pt: f64,
     refocus: f64,
+    opd_mode: OpdMode,
     lens_payload: &JsValue,
-) -> PSFResult {
+    field_payload: &JsValue,
+) -> Option<PSFResult> {
     set_panic_hook();
-    let lens: Lens = lens_payload.into_serde().unwrap();
-    //log(&format!("rusty {:?}", lens));
+    let e2ptsquared = source_e2pt * source_e2pt;
+    polychromatic_psf(
+        lens_payload,
+        field_payload,
+        wavelengths,
+        weights,
+        refocus,
+        opd_mode,
+        |opd_at, scale| {
+            let pupilsize = scaled_gridsize(gridsize, scale, totalsize);
+            let (amp, mask, num_failed) = sample_pupil(pupilsize, source_radius, opd_at, |r2| {
+                (-1.0 * r2 / e2ptsquared).exp()
+            });
+            let zero = gen_zero_2d(pupilsize);
+
+            let mut data = get_complex_vec(&amp, &mask, totalsize);
+            let mut datadl = get_complex_vec(&zero, &mask, totalsize);
+            let line = _rustfftmidline(&mut data, &mut datadl, gridsize);
+            (line, pupil_energy(&mask), num_failed)
+        },
+    )
+}
 
-    let mut amp = gen_zero_2d(gridsize);
-    let mut mask = gen_zero_2d(gridsize);
-    let zero = gen_zero_2d(gridsize);
+// launch rays of a field on the z = 0 plane
+#[derive(Serialize)]
+struct FieldRays {
+    chief: Ray,
+    marginal: Ray,
+}
 
-    let mut x: f64;
-    let mut y: f64;
-    let diag = source_radius * source_radius;
+#[wasm_bindgen(js_name = "fieldRays")]
+pub fn field_rays(wavelength: f64, lens_payload: &JsValue, field_payload: &JsValue) -> JsValue {
+    set_panic_hook();
+    let Some(lens) = lens_at(lens_payload, wavelength) else {
+        return JsValue::NULL;
+    };
+    let field = field_from(field_payload);
+    let rays = chief_ray(&field, &lens).and_then(|chief| {
+        marginal_ray(&field, &lens).map(|marginal| FieldRays { chief, marginal })
+    });
+    match rays {
+        Ok(rays) => JsValue::from_serde(&rays).unwrap(),
+        Err(err) => {
+            log(&format!("no field rays for {:?}: {:?}", field, err));
+            JsValue::NULL
+        }
+    }
+}
 
-    let step = 2.0 * source_radius / (gridsize - 1) as f64;
-    let e2ptsquared = source_e2pt * source_e2pt;
-    for row in 0..gridsize {
-        y = source_radius - row as f64 * step;
-        for col in 0..gridsize {
-            x = -source_radius + col as f64 * step;
-            let r2 = x * x + y * y;
-            if r2 < diag {
-                let p0 = Vector3D { x, y, z: 0.0 };
-                amp[row][col] = 2.0 * PI * calc_opd_slim(p0, CPROPV, &lens, wavelength, refocus); // multiply by 2pi to scale for fft
-                mask[row][col] = (-1.0 * r2 / e2ptsquared).exp();
-            } else {
-                amp[row][col] = 0.0;
-                mask[row][col] = 0.0;
+// paraxial first order properties of the lens at its object distance
+#[wasm_bindgen(js_name = "firstOrder")]
+pub fn first_order_properties(wavelength: f64, lens_payload: &JsValue) -> JsValue {
+    set_panic_hook();
+    match lens_at(lens_payload, wavelength) {
+        Some(lens) => JsValue::from_serde(&first_order(&lens)).unwrap(),
+        None => JsValue::NULL,
+    }
+}
+
+// per surface and total Seidel sums at a field, the chromatic terms use the dispersion of the
+// lens materials between the short and long wavelengths
+#[wasm_bindgen(js_name = "seidelAberrations")]
+pub fn seidel_aberrations(
+    wavelength: f64,
+    short_wavelength: f64,
+    long_wavelength: f64,
+    lens_payload: &JsValue,
+    field_payload: &JsValue,
+) -> JsValue {
+    set_panic_hook();
+    let Some(lens) = lens_at(lens_payload, wavelength) else {
+        return JsValue::NULL;
+    };
+    let field = field_from(field_payload);
+    match lens.dispersion(short_wavelength, long_wavelength) {
+        Ok(dispersion) => match seidel(&lens, &field, &dispersion) {
+            Ok(sums) => JsValue::from_serde(&sums).unwrap(),
+            Err(err) => {
+                log(&format!("no Seidel sums for the lens: {:?}", err));
+                JsValue::NULL
             }
+        },
+        Err(err) => {
+            log(&format!("no dispersion for the lens: {:?}", err));
+            JsValue::NULL
+        }
+    }
+}
+
+// paraxial image plane of each wavelength relative to the first, in lens units
+#[wasm_bindgen(js_name = "chromaticFocalShift")]
+pub fn chromatic_focal_shift(wavelengths: &[f64], lens_payload: &JsValue) -> Option<Vec<f64>> {
+    set_panic_hook();
+    let lens: Lens = lens_payload.into_serde().unwrap();
+    match focal_shift(&lens, wavelengths) {
+        Ok(shift) => Some(shift),
+        Err(err) => {
+            log(&format!("no focal shift: {:?}", err));
+            None
+        }
+    }
+}
+
+// Lateral color along the field, from the axis to the field payload in num_points steps. Each
+// point holds (dx, dy) of every wavelength relative to the first on the image plane of the
+// first wavelength, flattened point by point.
+#[wasm_bindgen(js_name = "lateralColor")]
+pub fn lateral_color_curve(
+    num_points: usize,
+    refocus: f64,
+    wavelengths: &[f64],
+    lens_payload: &JsValue,
+    field_payload: &JsValue,
+) -> Option<Vec<f64>> {
+    set_panic_hook();
+    let lens: Lens = lens_payload.into_serde().unwrap();
+    let field = field_from(field_payload);
+
+    let mut curve = Vec::with_capacity(num_points * wavelengths.len() * 2);
+    for i in 0..num_points {
+        let fraction = i as f64 / (num_points.max(2) - 1) as f64;
+        match lateral_color(&lens, wavelengths, &field.scaled(fraction), refocus) {
+            Ok(points) => curve.extend(points.iter().flat_map(|(dx, dy)| [*dx, *dy])),
+            Err(err) => {
+                log(&format!("no lateral color at {:?}: {:?}", field, err));
+                return None;
+            }
+        }
+    }
+    Some(curve)
+}
+
+#[derive(Serialize)]
+struct RayAberrations {
+    axial: AxialAberrations,
+    fields: Vec<FieldFans>,
+}
+
+// On axis TSA and LSA, and the tangential, sagittal and OPD fans of each field, at every
+// wavelength from num_points pupil positions across a pupil of pupil_radius on z = 0. The
+// curves of the other wavelengths are taken on the image plane of the first.
+#[wasm_bindgen(js_name = "rayAberrations")]
+pub fn ray_aberrations(
+    num_points: usize,
+    pupil_radius: f64,
+    refocus: f64,
+    wavelengths: &[f64],
+    lens_payload: &JsValue,
+    fields_payload: &JsValue,
+) -> JsValue {
+    set_panic_hook();
+    let Some(lenses) = lenses_for(lens_payload, wavelengths) else {
+        return JsValue::NULL;
+    };
+    let refocus = common_refocus(&lenses, refocus);
+
+    let aberrations = axial_aberrations(&lenses, &refocus, wavelengths, pupil_radius, num_points)
+        .and_then(|axial| {
+            let fields = fields_from(fields_payload)
+                .iter()
+                .map(|field| {
+                    field_fans(
+                        &lenses,
+                        &refocus,
+                        wavelengths,
+                        field,
+                        pupil_radius,
+                        num_points,
+                    )
+                })
+                .collect::<Result<Vec<FieldFans>, _>>()?;
+            Ok(RayAberrations { axial, fields })
+        });
+    match aberrations {
+        Ok(aberrations) => JsValue::from_serde(&aberrations).unwrap(),
+        Err(err) => {
+            log(&format!("no ray aberrations: {:?}", err));
+            JsValue::NULL
+        }
+    }
+}
+
+// Spot diagrams of each field at each wavelength, with their centroid, RMS and GEO radii and
+// the Airy radius, plus the weighted spot of the whole spectrum. num_rays rays spread over a
+// pupil of source_radius on the z = 0 plane around the chief ray, the same pupil points for
+// every field and wavelength.
+#[wasm_bindgen(js_name = "spotDiagram")]
+pub fn spot_diagram(
+    num_rays: usize,
+    source_radius: f64,
+    refocus: f64,
+    wavelengths: &[f64],
+    weights: &[f64],
+    lens_payload: &JsValue,
+    fields_payload: &JsValue,
+    sampling_payload: &JsValue,
+    seed: Option<u32>,
+) -> JsValue {
+    set_panic_hook();
+    let Some(lenses) = lenses_for(lens_payload, wavelengths) else {
+        return JsValue::NULL;
+    };
+    let refocus = common_refocus(&lenses, refocus);
+    let weights = normalized_weights(wavelengths.len(), weights);
+    let mut rng = source_rng(seed.map(u64::from));
+    let pupil = DiscSampler::new(sampling_from(sampling_payload).pupil).points(
+        num_rays,
+        source_radius,
+        &mut rng,
+    );
+
+    let spots = fields_from(fields_payload)
+        .iter()
+        .map(|field| {
+            field_spot(
+                &lenses,
+                &refocus,
+                wavelengths,
+                &weights,
+                field,
+                &pupil,
+                source_radius,
+            )
+        })
+        .collect::<Result<Vec<FieldSpot>, _>>();
+    match spots {
+        Ok(spots) => JsValue::from_serde(&spots).unwrap(),
+        Err(err) => {
+            log(&format!("no spot diagram: {:?}", err));
+            JsValue::NULL
+        }
+    }
+}
+
+// names of the built in materials and the loaded glasses
+#[wasm_bindgen(js_name = "materialNames")]
+pub fn material_names() -> JsValue {
+    JsValue::from_serde(&material::names()).unwrap()
+}
+
+#[derive(Serialize)]
+struct ZmxImport {
+    system: ZmxSystem,
+    warnings: Vec<ZmxWarning>,
+}
+
+// ZMX prescription to the system and the warnings about what could not be represented, null
+// (and a console message) when the file has no lens
+#[wasm_bindgen(js_name = "parseZmx")]
+pub fn parse_zmx_text(text: &str) -> JsValue {
+    set_panic_hook();
+    match parse_zmx(text) {
+        Ok((system, warnings)) => JsValue::from_serde(&ZmxImport { system, warnings }).unwrap(),
+        Err(err) => {
+            log(&format!("not a ZMX prescription: {:?}", err));
+            JsValue::NULL
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ZmxExport {
+    text: String,
+    warnings: Vec<ZmxWarning>,
+}
+
+// ZMX text of a system payload shaped like the system parseZmx returns
+#[wasm_bindgen(js_name = "writeZmx")]
+pub fn write_zmx_text(system_payload: &JsValue) -> JsValue {
+    set_panic_hook();
+    let system: ZmxSystem = system_payload.into_serde().unwrap();
+    match write_zmx(&system) {
+        Ok((text, warnings)) => JsValue::from_serde(&ZmxExport { text, warnings }).unwrap(),
+        Err(err) => {
+            log(&format!("cannot write a ZMX prescription: {:?}", err));
+            JsValue::NULL
         }
     }
+}
+
+// Load the glasses of a Zemax AGF catalog so surfaces can name them as materials. Returns the
+// number of glasses loaded, glasses that cannot be used are reported on the console.
+#[wasm_bindgen(js_name = "loadGlassCatalog")]
+pub fn load_glass_catalog(agf_text: &str, catalog_name: &str) -> usize {
+    set_panic_hook();
+    let (glasses, errors) = parse_agf(agf_text, catalog_name);
+    for err in errors {
+        log(&format!("{}: {:?}", catalog_name, err));
+    }
+    let num_glasses = glasses.len();
+    material::register(glasses);
+    num_glasses
+}
+
+// index of a material at the wavelength in microns and optionally a temperature in C,
+// undefined when it has no index there
+#[wasm_bindgen(js_name = "materialIndex")]
+pub fn material_index(name: &str, wavelength: f64, temperature: Option<f64>) -> Option<f64> {
+    set_panic_hook();
+    let n_index = Material::from_name(name).and_then(|material| match temperature {
+        Some(temperature) => material.n_index_at_temperature(wavelength, temperature),
+        None => material.n_index_at(wavelength),
+    });
+    match n_index {
+        Ok(n_index) => Some(n_index),
+        Err(err) => {
+            log(&format!("no index: {:?}", err));
+            None
+        }
+    }
+}
+
+// internal transmission of a glass through a thickness in mm, undefined outside its
+// transmission data
+#[wasm_bindgen(js_name = "materialTransmission")]
+pub fn material_transmission(name: &str, wavelength: f64, thickness: f64) -> Option<f64> {
+    set_panic_hook();
+    Material::from_name(name)
+        .ok()
+        .and_then(|material| material.internal_transmission(wavelength, thickness))
+}
+
+#[derive(Serialize)]
+struct AsphereConversion {
+    side: Side,
+    residual: f64,
+}
 
-    let mut data = get_complex_vec(&amp, &mask, totalsize);
-    let mut datadl = get_complex_vec(&zero, &mask, totalsize);
-    let dataout = _rustfftmidline(&mut data, &mut datadl, gridsize);
+#[wasm_bindgen(js_name = "toStandardAsphere")]
+pub fn to_standard_asphere(side_payload: &JsValue) -> JsValue {
+    set_panic_hook();
+    let side: Side = side_payload.into_serde().unwrap();
+    let (side, residual) = to_standard(&side);
+    JsValue::from_serde(&AsphereConversion { side, residual }).unwrap()
+}
 
-    PSFResult { data: dataout }
+#[wasm_bindgen(js_name = "toQconAsphere")]
+pub fn to_qcon_asphere(side_payload: &JsValue, norm_radius: f64) -> JsValue {
+    set_panic_hook();
+    let side: Side = side_payload.into_serde().unwrap();
+    let (side, residual) = to_qcon(&side, norm_radius);
+    JsValue::from_serde(&AsphereConversion { side, residual }).unwrap()
 }
 
+#[wasm_bindgen(js_name = "toQbfsAsphere")]
+pub fn to_qbfs_asphere(side_payload: &JsValue, norm_radius: f64, nterms: usize) -> JsValue {
+    set_panic_hook();
+    let side: Side = side_payload.into_serde().unwrap();
+    let (side, residual) = to_qbfs(&side, norm_radius, nterms);
+    JsValue::from_serde(&AsphereConversion { side, residual }).unwrap()
+}
+
+// xdata holds the bin positions, ydata the profile along the x axis and yaxis_data the
+// profile along the y axis. The two cuts are kept separate for anamorphic systems.
 #[wasm_bindgen]
 pub struct ExtSrcResult {
     xdata: Vec<f64>,
     ydata: Vec<f64>,
+    yaxis_data: Vec<f64>,
     num_errors: i32,
+    num_failed: usize,
 }
 
 #[wasm_bindgen]
@@ -294,7 +1044,9 @@ impl ExtSrcResult {
         ExtSrcResult {
             xdata: vec![],
             ydata: vec![],
+            yaxis_data: vec![],
             num_errors: i32::MIN,
+            num_failed: 0,
         }
     }
 
@@ -318,12 +1070,30 @@ impl ExtSrcResult {
         self.ydata.len()
     }
 
+    #[wasm_bindgen(getter, js_name = "yaxisDataPtr")]
+    pub fn yaxis_data_ptr(&self) -> *const f64 {
+        self.yaxis_data.as_ptr()
+    }
+
+    #[wasm_bindgen(getter, js_name = "yaxisDataSize")]
+    pub fn yaxis_data_size(&self) -> usize {
+        self.yaxis_data.len()
+    }
+
     #[wasm_bindgen(getter, js_name = "numErrors")]
     pub fn num_errors(&self) -> i32 {
         self.num_errors
     }
+
+    // rays vignetted or failed in the lens, numErrors only counts rays that land off the bins
+    #[wasm_bindgen(getter, js_name = "numFailed")]
+    pub fn num_failed(&self) -> usize {
+        self.num_failed
+    }
 }
 
+// Each ray is binned with the normalized weight of its wavelength, so the profiles keep their
+// scale for any spectrum.
 #[wasm_bindgen(js_name = "runExtSrcTrace")]
 pub fn run_extsource(
     num_rays: usize,
@@ -334,26 +1104,63 @@ pub fn run_extsource(
     sbins: usize, // this value should be odd
     multiplier: f64,
     use_fermi: bool,
+    wavelengths: &[f64],
+    weights: &[f64],
     lens_payload: &JsValue,
-) -> ExtSrcResult {
+    field_payload: &JsValue,
+    sampling_payload: &JsValue,
+    seed: Option<u32>,
+) -> Option<ExtSrcResult> {
     set_panic_hook();
 
-    let lens: Lens = lens_payload.into_serde().unwrap();
+    let lenses = lenses_for(lens_payload, wavelengths)?;
+    let refocus = common_refocus(&lenses, refocus);
+    let weights = normalized_weights(wavelengths.len(), weights);
     // log(&format!("rusty {:?}", lens));
     //log(&format!("rusty {:?}", source_radius));
 
-    let half_ang = fiber_radius / lens.efl();
-
-    let in_rays = gen_random_rays(num_rays, num_angles, source_radius, half_ang);
+    let in_rays = gen_source_rays(
+        num_rays,
+        num_angles,
+        fiber_radius,
+        source_radius,
+        &lenses[0],
+        &sampling_from(sampling_payload),
+        seed,
+    );
     // let out_rays = Vec::with_capacity(num_rays * num_angles);
 
-    let mut p_vecs = Vec::with_capacity(in_rays.len() * 2);
-
-    for in_r in &in_rays {
-        let out_r = trace_ray(&in_r, &lens, refocus);
-        p_vecs.push(out_r.pvector.x);
-        p_vecs.push(out_r.pvector.y);
-        //p_vecs.push(out_r.pvector.z);
+    let chief = field_chief(field_payload, &lenses[0]);
+    // histogram about the chief ray image point of the first wavelength
+    let center = chief
+        .as_ref()
+        .and_then(|chief| trace_ray(chief, &lenses[0], refocus[0]).ok())
+        .map_or(
+            Vector3D {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            |r| r.pvector,
+        );
+
+    let mut p_vecs = Vec::with_capacity(in_rays.len() * lenses.len() * 3);
+    let mut num_failed = 0;
+
+    for ((lens, refocus), weight) in lenses.iter().zip(refocus).zip(weights) {
+        for in_r in &in_rays {
+            let traced = chief
+                .as_ref()
+                .map(|chief| trace_ray(&orient_to_chief(in_r, chief, lens), lens, refocus));
+            let Some(Ok(out_r)) = traced else {
+                num_failed += 1;
+                continue;
+            };
+            p_vecs.push(out_r.pvector.x - center.x);
+            p_vecs.push(out_r.pvector.y - center.y);
+            p_vecs.push(weight);
+            //p_vecs.push(out_r.pvector.z);
+        }
     }
 
     let nbins = sbins as f64;
@@ -363,27 +1170,31 @@ pub fn run_extsource(
 
     let (datamap, errors) =
         process_rust_ray_data(&p_vecs, fiber_radius, sbins, multiplier as usize);
-    let (xs, ys) = cull_vector3d_data(&datamap, cell_size, vscale, num_rays * num_angles);
+    let (xdata, xcut, ycut) = cull_vector3d_data(&datamap, cell_size, vscale, in_rays.len());
 
-    // find max ys value
+    // find max value of either cut
     let mut max_y = 0.0;
-    for y in &ys {
+    for y in xcut.iter().chain(ycut.iter()) {
         if *y > max_y {
             max_y = *y;
         }
     }
     // this snippet is thanks to ChatGPT
-    let (xdata, ydata) = if use_fermi && max_y > 0.6 {
-        let yfermi = fittofermi_dirac(&xs, &ys, 20.0, 0.1, 1.0);
-        mirror_data_array(&xs, &yfermi)
+    let (ydata, yaxis_data) = if use_fermi && max_y > 0.6 {
+        (
+            fittofermi_dirac(&xdata, &xcut, 20.0, 0.1, 1.0),
+            fittofermi_dirac(&xdata, &ycut, 20.0, 0.1, 1.0),
+        )
     } else {
-        mirror_data_array(&xs, &ys)
+        (xcut, ycut)
     };
-    return ExtSrcResult {
+    return Some(ExtSrcResult {
         xdata,
         ydata,
+        yaxis_data,
         num_errors: errors as i32,
-    };
+        num_failed,
+    });
 }
 
 fn process_rust_ray_data(
@@ -401,11 +1212,12 @@ fn process_rust_ray_data(
     let mut indata = vec![vec![0.0; sbins]; sbins];
     let mut errors = 0;
 
-    for chunk in vlist.chunks_exact(2) {
+    // (x, y, weight) per ray
+    for chunk in vlist.chunks_exact(3) {
         let row = ((chunk[0] - min_xy) / binsize).floor() as isize;
         let col = ((chunk[1] - min_xy) / binsize).floor() as isize;
         if row >= 0 && row < num_bins && col >= 0 && col < num_bins {
-            indata[row as usize][col as usize] += 1.0;
+            indata[row as usize][col as usize] += chunk[2];
         } else {
             errors += 1;
         }
@@ -439,54 +1251,30 @@ fn _cull_vector3d_data2(
     (xs, ys)
 }
 
+// returns bin positions and the center row and center column of the binned data
 fn cull_vector3d_data(
     data: &Vec<Vec<f64>>,
     xstep: f64,
     vscale: f64,
     totalrays: usize,
-) -> (Vec<f64>, Vec<f64>) {
-    let cpt = data[0].len() / 2; // if passing an even array???
-
-    let mut ytemp: Vec<f64> = Vec::new();
-    let mut ys: Vec<f64> = Vec::new();
+) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    let nbins = data[0].len();
+    let cpt = nbins / 2; // if passing an even array???
 
     // generate x data
-    let xs = (0..cpt).map(|i| xstep * i as f64).collect::<Vec<f64>>();
-
-    // average center row and center columns to smooth data
-    for i in 0..data[0].len() {
-        ytemp.push(vscale * (data[i][cpt] + data[cpt][i]) / (2.0 * totalrays as f64));
-    }
-
-    // Average front half and back have of line data
-    for i in 0..cpt {
-        ys.push((ytemp[cpt - i] + ytemp[cpt + i]) / 2.0);
-    }
-
-    (xs, ys)
-}
-
-// not sure this is the best way to do this.
-// this function will take an x data array with values from 0.0 to some number +xi
-// and create mirrored arrays for -xi to xi
-// -xi & xi have the same y values
-fn mirror_data_array(xs: &[f64], ys: &[f64]) -> (Vec<f64>, Vec<f64>) {
-    let mut xdata = vec![0.0; xs.len() * 2];
-    let mut ydata = vec![0.0; ys.len() * 2];
-
-    let midpt = xs.len();
-
-    for i in 0..xs.len() {
-        xdata[midpt + i] = xs[i];
-        ydata[midpt + i] = ys[i];
-        xdata[midpt - i] = -xs[i];
-        ydata[midpt - i] = ys[i];
-    }
+    let xs = (0..nbins)
+        .map(|i| xstep * (i as f64 - cpt as f64))
+        .collect::<Vec<f64>>();
 
-    xdata.remove(0);
-    ydata.remove(0);
+    // rows are binned by x, so the center column is the cut along the x axis
+    let xcut = (0..nbins)
+        .map(|i| vscale * data[i][cpt] / totalrays as f64)
+        .collect::<Vec<f64>>();
+    let ycut = (0..nbins)
+        .map(|i| vscale * data[cpt][i] / totalrays as f64)
+        .collect::<Vec<f64>>();
 
-    (xdata, ydata)
+    (xs, xcut, ycut)
 }
 
 // average center rows
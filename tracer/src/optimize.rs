@@ -45,7 +45,7 @@ pub fn opti_lens(ray_ys: &[f64], lensin: Lens, optv: &OptVariables) -> Lens {
             for _i in 0..loopsize {
                 if optv.iscc1on == 1 {
                     cc = finddirection(&mut lens, &rays, deltk, 0);
-                    lens.side1.k += cc;
+                    lens.surfaces[0].side.k += cc;
                     if cc.abs() < mintweakstep {
                         deltk /= 10.0;
                     }
@@ -53,7 +53,7 @@ pub fn opti_lens(ray_ys: &[f64], lensin: Lens, optv: &OptVariables) -> Lens {
 
                 if optv.isad1on == 1 {
                     ad = finddirection(&mut lens, &rays, deltad, 1);
-                    lens.side1.ad += ad;
+                    *lens.surfaces[0].side.coeff_mut(0) += ad;
                     if ad.abs() < mintweakstep {
                         deltad /= 10.0;
                     }
@@ -61,7 +61,7 @@ pub fn opti_lens(ray_ys: &[f64], lensin: Lens, optv: &OptVariables) -> Lens {
 
                 if optv.isae1on == 1 {
                     ae = finddirection(&mut lens, &rays, deltae, 2);
-                    lens.side1.ae += ae;
+                    *lens.surfaces[0].side.coeff_mut(1) += ae;
                     if ae.abs() < mintweakstep {
                         deltae /= 10.0;
                     }
@@ -69,7 +69,7 @@ pub fn opti_lens(ray_ys: &[f64], lensin: Lens, optv: &OptVariables) -> Lens {
 
                 if optv.iscc2on == 1 {
                     cc = finddirection(&mut lens, &rays, deltk, 3);
-                    lens.side2.k += cc;
+                    lens.surfaces[1].side.k += cc;
                     if cc.abs() < mintweakstep {
                         deltk /= 10.0;
                     }
@@ -77,7 +77,7 @@ pub fn opti_lens(ray_ys: &[f64], lensin: Lens, optv: &OptVariables) -> Lens {
 
                 if optv.isad2on == 1 {
                     ad = finddirection(&mut lens, &rays, deltad, 4);
-                    lens.side2.ad += ad;
+                    *lens.surfaces[1].side.coeff_mut(0) += ad;
                     if ad.abs() < mintweakstep {
                         deltad /= 10.0;
                     }
@@ -85,7 +85,7 @@ pub fn opti_lens(ray_ys: &[f64], lensin: Lens, optv: &OptVariables) -> Lens {
 
                 if optv.isae2on == 1 {
                     ae = finddirection(&mut lens, &rays, deltae, 5);
-                    lens.side2.ae += ae;
+                    *lens.surfaces[1].side.coeff_mut(1) += ae;
                     if ae.abs() < mintweakstep {
                         deltae /= 10.0;
                     }
@@ -99,12 +99,12 @@ pub fn opti_lens(ray_ys: &[f64], lensin: Lens, optv: &OptVariables) -> Lens {
 
 pub fn finddirection(lens: &mut Lens, rays: &Vec<Ray>, delta_x: f64, whichvar: i32) -> f64 {
     let raw_ptr: *mut f64 = match whichvar {
-        0 => &mut lens.side1.k,
-        1 => &mut lens.side1.ad,
-        2 => &mut lens.side1.ae,
-        3 => &mut lens.side2.k,
-        4 => &mut lens.side2.ad,
-        5 => &mut lens.side2.ae,
+        0 => &mut lens.surfaces[0].side.k,
+        1 => lens.surfaces[0].side.coeff_mut(0),
+        2 => lens.surfaces[0].side.coeff_mut(1),
+        3 => &mut lens.surfaces[1].side.k,
+        4 => lens.surfaces[1].side.coeff_mut(0),
+        5 => lens.surfaces[1].side.coeff_mut(1),
         _ => ptr::null_mut(),
     };
 
@@ -115,17 +115,17 @@ pub fn finddirection(lens: &mut Lens, rays: &Vec<Ray>, delta_x: f64, whichvar: i
     /*
     let raw_ptr: *mut f64;
     if whichvar == 0 {
-        raw_ptr = &mut lens.side1.k;
+        raw_ptr = &mut lens.surfaces[0].side.k;
     } else if whichvar == 1 {
-        raw_ptr = &mut lens.side1.ad;
+        raw_ptr = lens.surfaces[0].side.coeff_mut(0);
     } else if whichvar == 2 {
-        raw_ptr = &mut lens.side1.ae;
+        raw_ptr = lens.surfaces[0].side.coeff_mut(1);
     } else if whichvar == 3 {
-        raw_ptr = &mut lens.side2.k;
+        raw_ptr = &mut lens.surfaces[1].side.k;
     } else if whichvar == 4 {
-        raw_ptr = &mut lens.side2.ad;
+        raw_ptr = lens.surfaces[1].side.coeff_mut(0);
     } else if whichvar == 5 {
-        raw_ptr = &mut lens.side2.ae;
+        raw_ptr = lens.surfaces[1].side.coeff_mut(1);
     } else {
         return 0.0;
     }
@@ -153,12 +153,15 @@ pub fn finddirection(lens: &mut Lens, rays: &Vec<Ray>, delta_x: f64, whichvar: i
 }
 
 pub fn calc_err_slim(lens: &Lens, rays: &Vec<Ray>) -> f64 {
-    let rayct = rays.len();
+    let mut rayct = 0;
     let mut sumsum: f64 = 0.0;
 
-    for i in 0..rayct {
-        let rayout = trace_ray(&rays[i], &lens, 0.0_f64);
-        sumsum += rayout.pvector.y * rayout.pvector.y;
+    // vignetted and failed rays do not contribute
+    for ray in rays {
+        if let Ok(rayout) = trace_ray(ray, &lens, 0.0_f64) {
+            sumsum += rayout.pvector.y * rayout.pvector.y;
+            rayct += 1;
+        }
     }
     return (sumsum / rayct as f64).sqrt();
 }
@@ -0,0 +1,321 @@
+// Paraxial y-nu trace of sequential systems and their first order properties.
+// Reduced angles are nu = n u. Indices carry the direction of travel, a mirror flips the sign
+// and the thickness after it is negative. Object space is air, positions are z along the lens
+// axis with the first vertex at 0.
+
+use crate::lens::{Lens, Surface};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParaxialRay {
+    pub y: f64,
+    pub nu: f64,
+}
+
+impl ParaxialRay {
+    pub fn new(y: f64, nu: f64) -> ParaxialRay {
+        ParaxialRay { y, nu }
+    }
+
+    fn transfer(self, thickness: f64, n: f64) -> ParaxialRay {
+        ParaxialRay::new(self.y + thickness * self.nu / n, self.nu)
+    }
+
+    fn refract(self, surf: &Surface, n: f64, n_next: f64) -> ParaxialRay {
+        ParaxialRay::new(self.y, self.nu - self.y * surf.side.curv() * (n_next - n))
+    }
+
+    // signed distance along z from the current vertex to where the ray crosses the axis
+    pub fn axis_crossing(&self, n: f64) -> f64 {
+        -self.y * n / self.nu
+    }
+
+    // height after travelling dz along z
+    pub fn height_at(&self, dz: f64, n: f64) -> f64 {
+        self.y + dz * self.nu / n
+    }
+}
+
+// index after a surface, mirrors flip the sign
+pub fn signed_index(surf: &Surface, n_before: f64) -> f64 {
+    if surf.mirror {
+        -n_before.signum() * surf.n_index
+    } else {
+        n_before.signum() * surf.n_index
+    }
+}
+
+// signed index after a run of surfaces entered from index n
+pub fn index_after(surfaces: &[Surface], n: f64) -> f64 {
+    surfaces.iter().fold(n, |n, surf| signed_index(surf, n))
+}
+
+// trace from just before the first surface to just after the last, the ray is left at the
+// last vertex. Returns the ray and the index it ends in.
+pub fn trace(surfaces: &[Surface], ray: ParaxialRay, n: f64) -> (ParaxialRay, f64) {
+    let mut ray = ray;
+    let mut n = n;
+    let last = surfaces.len().saturating_sub(1);
+
+    for (i, surf) in surfaces.iter().enumerate() {
+        let n_next = signed_index(surf, n);
+        ray = ray.refract(surf, n, n_next);
+        n = n_next;
+        if i < last {
+            ray = ray.transfer(surf.thickness, n);
+        }
+    }
+    (ray, n)
+}
+
+// height at the vertex of a surface, before it refracts, of a ray launched at the first surface
+pub fn height_at_surface(surfaces: &[Surface], surface: usize, ray: ParaxialRay) -> f64 {
+    if surface == 0 {
+        return ray.y;
+    }
+    let (ray, n) = trace(&surfaces[..surface], ray, 1.);
+    ray.transfer(surfaces[surface - 1].thickness, n).y
+}
+
+// index of the aperture stop, the first surface when none is set
+pub fn stop_surface(lens: &Lens) -> usize {
+    lens.stop
+        .unwrap_or(0)
+        .min(lens.surfaces.len().saturating_sub(1))
+}
+
+// z position of the exit pupil, the chief ray from the stop center traced into image space.
+// Infinite for an image space telecentric system.
+pub fn exit_pupil(lens: &Lens) -> f64 {
+    let stop = stop_surface(lens);
+    let n_stop = index_after(&lens.surfaces[..stop], 1.);
+    let (chief, n) = trace(&lens.surfaces[stop..], ParaxialRay::new(0., 1.), n_stop);
+    lens.track() + chief.axis_crossing(n)
+}
+
+// heights at the stop of the unit height and unit angle rays launched at the first surface
+fn stop_heights(lens: &Lens) -> (f64, f64) {
+    let stop = stop_surface(lens);
+    (
+        height_at_surface(&lens.surfaces, stop, ParaxialRay::new(1., 0.)),
+        height_at_surface(&lens.surfaces, stop, ParaxialRay::new(0., 1.)),
+    )
+}
+
+// z position of the entrance pupil, where the ray through the stop center crosses the axis in
+// object space
+pub fn entrance_pupil(lens: &Lens) -> f64 {
+    let (stop_y, stop_nu) = stop_heights(lens);
+    stop_nu / stop_y
+}
+
+// axial ray from the object filling the stop, launched at the first surface
+pub fn marginal_ray(lens: &Lens) -> ParaxialRay {
+    let (stop_y, stop_nu) = stop_heights(lens);
+    let stop_radius = lens.clear_radius(stop_surface(lens)).unwrap_or(0.);
+    match lens.object_distance {
+        None => ParaxialRay::new(stop_radius / stop_y, 0.),
+        Some(dist) => {
+            let u = stop_radius / (dist * stop_y + stop_nu);
+            ParaxialRay::new(dist * u, u)
+        }
+    }
+}
+
+// ray through the stop center launched at the first surface. The field is the tangent of the
+// field angle for an object at infinity and the object height otherwise.
+pub fn chief_ray(lens: &Lens, field: f64) -> ParaxialRay {
+    let pupil = entrance_pupil(lens);
+    let u = match lens.object_distance {
+        None => field,
+        Some(dist) => -field / (pupil + dist),
+    };
+    ParaxialRay::new(-u * pupil, u)
+}
+
+// First order properties. Focal lengths are signed, the ffl is positive when the front focal
+// point is in front of the first vertex. Plane and pupil positions are z positions. Infinite
+// values (afocal or telecentric systems) serialize to null.
+#[derive(Debug, Clone, Serialize)]
+pub struct FirstOrder {
+    pub efl: f64,
+    pub bfl: f64,
+    pub ffl: f64,
+    pub front_focal_plane: f64,
+    pub back_focal_plane: f64,
+    pub front_principal_plane: f64,
+    pub back_principal_plane: f64,
+    pub front_nodal_plane: f64,
+    pub back_nodal_plane: f64,
+    pub entrance_pupil: f64,
+    pub entrance_pupil_diameter: f64,
+    pub exit_pupil: f64,
+    pub exit_pupil_diameter: f64,
+    // infinite conjugate f-number, efl over the entrance pupil diameter
+    pub f_number: f64,
+    // 1 / (2 NA) in image space at the working conjugates
+    pub working_f_number: f64,
+    pub object_na: f64,
+    pub image_na: f64,
+    pub image_distance: f64,
+    pub magnification: f64,
+}
+
+pub fn first_order(lens: &Lens) -> FirstOrder {
+    let surfaces = &lens.surfaces;
+    let track = lens.track();
+
+    // system matrix from the columns traced for unit height and unit angle rays
+    let (ray_y, n_image) = trace(surfaces, ParaxialRay::new(1., 0.), 1.);
+    let (ray_nu, _) = trace(surfaces, ParaxialRay::new(0., 1.), 1.);
+    let (a, c) = (ray_y.y, ray_y.nu);
+    let d = ray_nu.nu;
+
+    let efl = -1. / c;
+    let bfl = -a * n_image / c;
+    let front_focal_plane = d / c;
+    let back_focal_plane = track + bfl;
+
+    let entrance_pupil = entrance_pupil(lens);
+    let exit_pupil = exit_pupil(lens);
+
+    let marginal = marginal_ray(lens);
+    let entrance_pupil_diameter = 2. * marginal.height_at(entrance_pupil, 1.).abs();
+    let (marginal_image, _) = trace(surfaces, marginal, 1.);
+    let exit_pupil_diameter = 2. * marginal_image.height_at(exit_pupil - track, n_image).abs();
+    let image_na = marginal_image.nu.abs();
+
+    FirstOrder {
+        efl,
+        bfl,
+        ffl: -front_focal_plane,
+        front_focal_plane,
+        back_focal_plane,
+        front_principal_plane: front_focal_plane + efl,
+        back_principal_plane: back_focal_plane - n_image * efl,
+        front_nodal_plane: front_focal_plane + n_image * efl,
+        back_nodal_plane: back_focal_plane - efl,
+        entrance_pupil,
+        entrance_pupil_diameter,
+        exit_pupil,
+        exit_pupil_diameter,
+        f_number: (efl / entrance_pupil_diameter).abs(),
+        working_f_number: 1. / (2. * image_na),
+        object_na: marginal.nu.abs(),
+        image_na,
+        image_distance: lens.image_distance(),
+        magnification: lens.magnification(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lens::Side;
+
+    fn plano(thickness: f64, n_index: f64) -> Surface {
+        Surface::new(Side::new(0., 0., vec![]), thickness, n_index)
+    }
+
+    #[test]
+    fn thick_lens_cardinal_points() {
+        let (r1, r2, ct, n) = (50., -80., 6., 1.5);
+        let lens = Lens::singlet(
+            25.,
+            24.,
+            ct,
+            n,
+            Side::new(r1, 0., vec![]),
+            Side::new(r2, 0., vec![]),
+        );
+        let fo = first_order(&lens);
+
+        // textbook principal plane offsets from each vertex
+        let power = (n - 1.) * (1. / r1 - 1. / r2 + (n - 1.) * ct / (n * r1 * r2));
+        let efl = 1. / power;
+        let h1 = -efl * (n - 1.) * ct / (n * r2);
+        let h2 = -efl * (n - 1.) * ct / (n * r1);
+
+        assert!((fo.efl - efl).abs() < 1e-9);
+        assert!((fo.front_principal_plane - h1).abs() < 1e-9);
+        assert!((fo.back_principal_plane - (ct + h2)).abs() < 1e-9);
+        assert!((fo.ffl - (efl - h1)).abs() < 1e-9);
+        assert!((fo.bfl - (efl + h2)).abs() < 1e-9);
+        // in air the nodal points are the principal points
+        assert!((fo.front_nodal_plane - fo.front_principal_plane).abs() < 1e-9);
+        assert!((fo.back_nodal_plane - fo.back_principal_plane).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stop_in_front_of_a_thin_lens() {
+        // thin f = 50 lens with a 10 mm stop 25 in front of it
+        let mut stop = plano(25., 1.);
+        stop.clear_ap = Some(10.);
+        let mut lens = Lens::new(
+            25.,
+            24.,
+            vec![
+                stop,
+                Surface::new(Side::new(50., 0., vec![]), 0., 1.5),
+                Surface::new(Side::new(-50., 0., vec![]), 0., 1.),
+            ],
+        );
+        lens.stop = Some(0);
+        let fo = first_order(&lens);
+
+        assert!(fo.entrance_pupil.abs() < 1e-12);
+        assert!((fo.entrance_pupil_diameter - 10.).abs() < 1e-9);
+        assert!((fo.f_number - 5.).abs() < 1e-9);
+        assert!((fo.image_na - 0.1).abs() < 1e-9);
+        // virtual stop image 50 in front of the lens, magnified by 2
+        assert!((fo.exit_pupil - (25. - 50.)).abs() < 1e-9);
+        assert!((fo.exit_pupil_diameter - 20.).abs() < 1e-9);
+
+        // the stop behind the lens is seen through it
+        lens.surfaces = vec![
+            Surface::new(Side::new(50., 0., vec![]), 0., 1.5),
+            Surface::new(Side::new(-50., 0., vec![]), 25., 1.),
+            plano(0., 1.),
+        ];
+        lens.surfaces[2].clear_ap = Some(10.);
+        lens.stop = Some(2);
+        let fo = first_order(&lens);
+        assert!((fo.entrance_pupil - 50.).abs() < 1e-9);
+        assert!((fo.entrance_pupil_diameter - 20.).abs() < 1e-9);
+        assert!((fo.exit_pupil - 25.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn finite_conjugate_apertures() {
+        // thin f = 50 lens at 1:1, the lens is the stop
+        let mut lens = Lens::singlet(
+            20.,
+            20.,
+            0.,
+            1.5,
+            Side::new(50., 0., vec![]),
+            Side::new(-50., 0., vec![]),
+        );
+        lens.object_distance = Some(100.);
+        let fo = first_order(&lens);
+
+        assert!((fo.object_na - 0.1).abs() < 1e-9);
+        assert!((fo.image_na - 0.1).abs() < 1e-9);
+        assert!((fo.working_f_number - 5.).abs() < 1e-9);
+        assert!((fo.magnification + 1.).abs() < 1e-9);
+        assert!((fo.image_distance - 100.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mirror_principal_plane_at_vertex() {
+        let mut mirror = plano(0., 1.);
+        mirror.side = Side::new(-100., 0., vec![]);
+        mirror.mirror = true;
+        let fo = first_order(&Lens::new(25., 24., vec![mirror]));
+
+        assert!((fo.efl - 50.).abs() < 1e-9);
+        assert!((fo.back_focal_plane + 50.).abs() < 1e-9);
+        assert!(fo.back_principal_plane.abs() < 1e-9);
+        assert!((fo.front_focal_plane + 50.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,388 @@
+// Forbes Q-type asphere polynomials.
+// Qcon: z = conic + u^4 * sum(a_m * Qcon_m(u^2))
+// Qbfs: z = sphere + u^2 (1 - u^2) / sqrt(1 - c^2 r^2) * sum(a_m * Qbfs_m(u^2))
+// where u = r / norm_radius. See G. W. Forbes, "Shape specification for axially symmetric
+// optical surfaces", Opt. Express 15, 5218 (2007).
+
+use crate::lens::{AsphereDefinition, Side};
+
+// sum(a_m * Qcon_m(x)) and its derivative with respect to x.
+// Qcon_m(x) is the Jacobi polynomial P_m^(0,4)(2x - 1), evaluated by its three term recurrence.
+pub fn qcon_sum(coeffs: &[f64], x: f64) -> (f64, f64) {
+    let t = 2.0 * x - 1.0;
+    let (mut p0, mut d0) = (1.0, 0.0);
+    let (mut p1, mut d1) = (3.0 * t - 2.0, 6.0);
+    let mut sum = 0.0;
+    let mut dsum = 0.0;
+
+    for (m, a) in coeffs.iter().enumerate() {
+        let (p, d) = match m {
+            0 => (p0, d0),
+            1 => (p1, d1),
+            _ => {
+                let (a1, b1, c1) = jacobi_04(m as f64);
+                let p = (a1 * t + b1) * p1 - c1 * p0;
+                // chain rule, dt/dx = 2
+                let d = 2.0 * a1 * p1 + (a1 * t + b1) * d1 - c1 * d0;
+                p0 = p1;
+                d0 = d1;
+                p1 = p;
+                d1 = d;
+                (p, d)
+            }
+        };
+        sum += a * p;
+        dsum += a * d;
+    }
+    (sum, dsum)
+}
+
+// recurrence terms for P_n^(0,4), P_n = (a t + b) P_n-1 - c P_n-2
+fn jacobi_04(n: f64) -> (f64, f64, f64) {
+    let (alpha, beta) = (0.0, 4.0);
+    let s = 2.0 * n + alpha + beta;
+    let denom = 2.0 * n * (n + alpha + beta) * (s - 2.0);
+    let a = (s - 1.0) * s * (s - 2.0) / denom;
+    let b = (s - 1.0) * (alpha * alpha - beta * beta) / denom;
+    let c = 2.0 * (n + alpha - 1.0) * (n + beta - 1.0) * s / denom;
+    (a, b, c)
+}
+
+// sum(a_m * Qbfs_m(x)) and its derivative with respect to x, using the recurrence from
+// G. W. Forbes, "Robust, efficient computational methods for axially symmetric optical
+// aspheres", Opt. Express 18, 19700 (2010).
+pub fn qbfs_sum(coeffs: &[f64], x: f64) -> (f64, f64) {
+    let mut sum = 0.0;
+    let mut dsum = 0.0;
+
+    // P_m = (2 - 4x) P_m-1 - P_m-2 with P_0 = 2, P_1 = 6 - 8x
+    let (mut pm2, mut dpm2) = (0.0, 0.0);
+    let (mut pm1, mut dpm1) = (0.0, 0.0);
+    // Q_m = (P_m - g_m-1 Q_m-1 - h_m-2 Q_m-2) / f_m
+    let (mut qm2, mut dqm2) = (0.0, 0.0);
+    let (mut qm1, mut dqm1) = (0.0, 0.0);
+    let (mut f, mut g, mut h) = (vec![], vec![], vec![]);
+
+    for (m, a) in coeffs.iter().enumerate() {
+        let (p, dp) = match m {
+            0 => (2.0, 0.0),
+            1 => (6.0 - 8.0 * x, -8.0),
+            _ => (
+                (2.0 - 4.0 * x) * pm1 - pm2,
+                -4.0 * pm1 + (2.0 - 4.0 * x) * dpm1 - dpm2,
+            ),
+        };
+
+        let (q, dq) = match m {
+            0 => {
+                f.push(2.0);
+                g.push(-0.5);
+                (p / f[0], dp / f[0])
+            }
+            1 => {
+                f.push(19.0_f64.sqrt() / 2.0);
+                ((p - g[0] * qm1) / f[1], (dp - g[0] * dqm1) / f[1])
+            }
+            _ => {
+                let mf = m as f64;
+                h.push(-mf * (mf - 1.0) / (2.0 * f[m - 2]));
+                g.push(-(1.0 + g[m - 2] * h[m - 2]) / f[m - 1]);
+                f.push((mf * (mf + 1.0) + 3.0 - g[m - 1].powi(2) - h[m - 2].powi(2)).sqrt());
+                (
+                    (p - g[m - 1] * qm1 - h[m - 2] * qm2) / f[m],
+                    (dp - g[m - 1] * dqm1 - h[m - 2] * dqm2) / f[m],
+                )
+            }
+        };
+
+        sum += a * q;
+        dsum += a * dq;
+
+        pm2 = pm1;
+        dpm2 = dpm1;
+        pm1 = p;
+        dpm1 = dp;
+        qm2 = qm1;
+        dqm2 = dqm1;
+        qm1 = q;
+        dqm1 = dq;
+    }
+    (sum, dsum)
+}
+
+// monomial coefficients of Qcon_0..Qcon_n-1 in x, row m holds Qcon_m
+fn qcon_monomials(n: usize) -> Vec<Vec<f64>> {
+    let mut rows: Vec<Vec<f64>> = Vec::with_capacity(n);
+    for m in 0..n {
+        let mut row = vec![0.0; n];
+        match m {
+            0 => row[0] = 1.0,
+            // 3t - 2 with t = 2x - 1
+            1 => {
+                row[0] = -5.0;
+                row[1] = 6.0;
+            }
+            _ => {
+                let (a, b, c) = jacobi_04(m as f64);
+                for j in 0..m {
+                    // (a (2x - 1) + b) * P_m-1
+                    row[j] += (b - a) * rows[m - 1][j] - c * rows[m - 2][j];
+                    row[j + 1] += 2.0 * a * rows[m - 1][j];
+                }
+            }
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+// Exact conversion of Qcon coefficients to the standard power series (coeffs[j] on r^(4 + 2j)).
+// Both forms span the same polynomials so no information is lost.
+pub fn qcon_to_standard(qcoeffs: &[f64], norm_radius: f64) -> Vec<f64> {
+    let n = qcoeffs.len();
+    let q = qcon_monomials(n);
+    let rn2 = norm_radius * norm_radius;
+
+    (0..n)
+        .map(|j| {
+            let b: f64 = (j..n).map(|m| qcoeffs[m] * q[m][j]).sum();
+            b / rn2.powi(j as i32 + 2)
+        })
+        .collect()
+}
+
+// Exact inverse of qcon_to_standard
+pub fn standard_to_qcon(coeffs: &[f64], norm_radius: f64) -> Vec<f64> {
+    let n = coeffs.len();
+    let q = qcon_monomials(n);
+    let rn2 = norm_radius * norm_radius;
+    let mut a = vec![0.0; n];
+
+    for j in (0..n).rev() {
+        let b = coeffs[j] * rn2.powi(j as i32 + 2);
+        let tail: f64 = (j + 1..n).map(|m| a[m] * q[m][j]).sum();
+        a[j] = (b - tail) / q[j][j];
+    }
+    a
+}
+
+// Convert a side to the equivalent standard even asphere. Qcon is exact; Qbfs departures
+// carry a 1 / sqrt(1 - c^2 r^2) factor so they are least squares fit with the same number
+// of terms over the normalization radius. Returns the side and the rms fit residual.
+// Odd, anamorphic and freeform surfaces have no rotational even power series equivalent and come
+// back unchanged with an infinite residual.
+pub fn to_standard(side: &Side) -> (Side, f64) {
+    match side.definition {
+        AsphereDefinition::Standard => (side.clone(), 0.0),
+        AsphereDefinition::Odd
+        | AsphereDefinition::Biconic { .. }
+        | AsphereDefinition::Toroid { .. }
+        | AsphereDefinition::XyPolynomial { .. }
+        | AsphereDefinition::Zernike { .. } => (side.clone(), f64::INFINITY),
+        AsphereDefinition::Qcon { norm_radius } => (
+            Side::new(side.r, side.k, qcon_to_standard(&side.coeffs, norm_radius)),
+            0.0,
+        ),
+        AsphereDefinition::Qbfs { norm_radius } => {
+            let nterms = side.coeffs.len() + 2;
+            let (coeffs, resid) = fit_departure(
+                |r2| side.poly_sag(r2),
+                norm_radius,
+                nterms,
+                |r2, m| (r2 / norm_radius.powi(2)).powi(m as i32 + 2),
+            );
+            let scaled = coeffs
+                .iter()
+                .enumerate()
+                .map(|(j, b)| b / norm_radius.powi(2 * j as i32 + 4))
+                .collect();
+            (Side::new(side.r, side.k, scaled), resid)
+        }
+    }
+}
+
+// Convert a standard (or Q-type) side into Qcon form over norm_radius. Exact unless the
+// input is Qbfs, returns the side and the rms residual of to_standard.
+pub fn to_qcon(side: &Side, norm_radius: f64) -> (Side, f64) {
+    let (std_side, resid) = to_standard(side);
+    if std_side.definition != AsphereDefinition::Standard {
+        return (std_side, resid);
+    }
+    let mut qside = Side::new(
+        std_side.r,
+        std_side.k,
+        standard_to_qcon(&std_side.coeffs, norm_radius),
+    );
+    qside.definition = AsphereDefinition::Qcon { norm_radius };
+    (qside, resid)
+}
+
+// Fit a side with a Qbfs surface over norm_radius. The best fit sphere passes through the
+// vertex and the edge sag, and the remaining departure is fit with nterms polynomials.
+// Returns the side and the rms fit residual, surfaces without rotational symmetry come back
+// unchanged with an infinite residual.
+pub fn to_qbfs(side: &Side, norm_radius: f64, nterms: usize) -> (Side, f64) {
+    if !side.is_rotational() {
+        return (side.clone(), f64::INFINITY);
+    }
+    // a conic that ends short of norm_radius has no sag to fit
+    let Some(zedge) = side
+        .conic_sag(norm_radius * norm_radius)
+        .map(|z| z + side.poly_sag(norm_radius * norm_radius))
+    else {
+        return (side.clone(), f64::INFINITY);
+    };
+    // both conics reach norm_radius, so they cover every sample inside it
+    let sag = |r2: f64| side.conic_sag(r2).unwrap_or(f64::NAN) + side.poly_sag(r2);
+    let cbfs = 2.0 * zedge / (norm_radius * norm_radius + zedge * zedge);
+    let sphere = Side::new(if cbfs == 0.0 { 0.0 } else { 1.0 / cbfs }, 0.0, vec![]);
+
+    let (coeffs, resid) = fit_departure(
+        |r2| sag(r2) - sphere.conic_sag(r2).unwrap_or(f64::NAN),
+        norm_radius,
+        nterms,
+        |r2, m| {
+            let x = r2 / norm_radius.powi(2);
+            let mut basis = vec![0.0; m + 1];
+            basis[m] = 1.0;
+            x * (1.0 - x) * qbfs_sum(&basis, x).0 / (1.0 - cbfs * cbfs * r2).sqrt()
+        },
+    );
+
+    let mut qside = Side::new(sphere.r, 0.0, coeffs);
+    qside.definition = AsphereDefinition::Qbfs { norm_radius };
+    (qside, resid)
+}
+
+// least squares fit of target(r2) = sum(w_m * basis(r2, m)) sampled over the radius
+fn fit_departure(
+    target: impl Fn(f64) -> f64,
+    norm_radius: f64,
+    nterms: usize,
+    basis: impl Fn(f64, usize) -> f64,
+) -> (Vec<f64>, f64) {
+    let nsamples = 8 * nterms.max(1) + 32;
+    let samples = (0..nsamples)
+        .map(|i| {
+            let r = norm_radius * i as f64 / (nsamples - 1) as f64;
+            r * r
+        })
+        .collect::<Vec<f64>>();
+
+    let mut ata = vec![vec![0.0; nterms]; nterms];
+    let mut atb = vec![0.0; nterms];
+    for r2 in &samples {
+        let row = (0..nterms).map(|m| basis(*r2, m)).collect::<Vec<f64>>();
+        let z = target(*r2);
+        for i in 0..nterms {
+            atb[i] += row[i] * z;
+            for j in 0..nterms {
+                ata[i][j] += row[i] * row[j];
+            }
+        }
+    }
+    let coeffs = solve_linear(ata, atb);
+
+    let sumsq: f64 = samples
+        .iter()
+        .map(|r2| {
+            let fit: f64 = (0..nterms).map(|m| coeffs[m] * basis(*r2, m)).sum();
+            (fit - target(*r2)).powi(2)
+        })
+        .sum();
+    (coeffs, (sumsq / nsamples as f64).sqrt())
+}
+
+// gaussian elimination with partial pivoting
+pub(crate) fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))
+            .unwrap();
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        if a[col][col] == 0.0 {
+            continue;
+        }
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let prow = &upper[col];
+        for (i, row) in lower.iter_mut().enumerate() {
+            let f = row[col] / prow[col];
+            for (x, p) in row[col..].iter_mut().zip(prow[col..].iter()) {
+                *x -= f * p;
+            }
+            b[col + 1 + i] -= f * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let tail: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = if a[row][row] == 0.0 {
+            0.0
+        } else {
+            (b[row] - tail) / a[row][row]
+        };
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qcon_low_orders() {
+        // Qcon_1(x) = -(5 - 6x), Qcon_2(x) = 15 - 14x(3 - 2x)
+        let x = 0.3;
+        assert!((qcon_sum(&[0., 1.], x).0 + (5. - 6. * x)).abs() < 1e-12);
+        assert!((qcon_sum(&[0., 0., 1.], x).0 - (15. - 14. * x * (3. - 2. * x))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn qbfs_low_orders() {
+        let x = 0.3;
+        let q1 = (13. - 16. * x) / 19_f64.sqrt();
+        assert!((qbfs_sum(&[0., 1.], x).0 - q1).abs() < 1e-12);
+
+        let h = 1e-6;
+        let c = [0.1, -0.2, 0.3, 0.05, -0.01];
+        let numeric = (qbfs_sum(&c, x + h).0 - qbfs_sum(&c, x - h).0) / (2. * h);
+        assert!((qbfs_sum(&c, x).1 - numeric).abs() < 1e-6);
+    }
+
+    #[test]
+    fn qcon_round_trip() {
+        let std = vec![1e-5, -2e-8, 3e-11, -4e-14, 5e-17];
+        let q = standard_to_qcon(&std, 12.5);
+        let back = qcon_to_standard(&q, 12.5);
+        for (a, b) in std.iter().zip(back.iter()) {
+            assert!(((a - b) / a).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn qcon_sag_matches_standard() {
+        let mut side = Side::new(30., -0.7, standard_to_qcon(&[2e-5, -1e-8, 4e-12], 10.));
+        side.definition = AsphereDefinition::Qcon { norm_radius: 10. };
+        let std = Side::new(30., -0.7, vec![2e-5, -1e-8, 4e-12]);
+
+        for r2 in [0., 9., 49., 100.] {
+            assert!((side.poly_sag(r2) - std.poly_sag(r2)).abs() < 1e-12);
+            assert!((side.poly_slope(r2) - std.poly_slope(r2)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn qbfs_refits_standard() {
+        let side = Side::new(25., -1., vec![1e-5, 2e-9]);
+        let (qside, resid) = to_qbfs(&side, 8., 6);
+        assert!(resid < 1e-9);
+
+        let sag = |s: &Side, r2: f64| s.conic_sag(r2).unwrap() + s.poly_sag(r2);
+        for r2 in [4., 25., 64.] {
+            assert!((sag(&qside, r2) - sag(&side, r2)).abs() < 1e-8);
+        }
+    }
+}
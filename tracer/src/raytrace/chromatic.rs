@@ -0,0 +1,135 @@
+// Multi wavelength support. The first wavelength of a spectrum is the reference: the other
+// wavelengths are traced to its image plane and their PSFs are sampled on its image grid.
+use super::{
+    field::{chief_ray, Field, FieldError},
+    trace_ray,
+};
+use crate::lens::Lens;
+use crate::material::MaterialError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChromaticError {
+    NoWavelengths,
+    Material(MaterialError),
+    Field(FieldError),
+}
+
+impl From<MaterialError> for ChromaticError {
+    fn from(err: MaterialError) -> Self {
+        ChromaticError::Material(err)
+    }
+}
+
+impl From<FieldError> for ChromaticError {
+    fn from(err: FieldError) -> Self {
+        ChromaticError::Field(err)
+    }
+}
+
+// weights scaled to sum to one, missing weights count as one and a spectrum without any
+// weight falls back to equal weights
+pub fn normalized_weights(num_wavelengths: usize, weights: &[f64]) -> Vec<f64> {
+    let weights = (0..num_wavelengths)
+        .map(|i| weights.get(i).copied().unwrap_or(1.0))
+        .collect::<Vec<f64>>();
+    let total: f64 = weights.iter().sum();
+    if total > 0.0 {
+        weights.iter().map(|w| w / total).collect()
+    } else {
+        vec![1.0 / num_wavelengths as f64; num_wavelengths]
+    }
+}
+
+// the lens with its materials evaluated at each wavelength
+pub fn lenses_at(lens: &Lens, wavelengths: &[f64]) -> Result<Vec<Lens>, ChromaticError> {
+    if wavelengths.is_empty() {
+        return Err(ChromaticError::NoWavelengths);
+    }
+    Ok(wavelengths
+        .iter()
+        .map(|w| lens.at_wavelength(*w))
+        .collect::<Result<Vec<Lens>, MaterialError>>()?)
+}
+
+// refocus of each lens that lands it on the image plane of the first lens at refocus
+pub fn common_refocus(lenses: &[Lens], refocus: f64) -> Vec<f64> {
+    let z = lenses[0].image_plane(refocus);
+    lenses.iter().map(|lens| lens.refocus_to(z)).collect()
+}
+
+// paraxial image plane of each wavelength relative to the first, measured along z
+pub fn focal_shift(lens: &Lens, wavelengths: &[f64]) -> Result<Vec<f64>, ChromaticError> {
+    let lenses = lenses_at(lens, wavelengths)?;
+    let z = lenses[0].image_plane(0.0);
+    Ok(lenses.iter().map(|l| l.image_plane(0.0) - z).collect())
+}
+
+// (x, y) of the chief ray of each wavelength relative to the first, on the image plane of the
+// first wavelength
+pub fn lateral_color(
+    lens: &Lens,
+    wavelengths: &[f64],
+    field: &Field,
+    refocus: f64,
+) -> Result<Vec<(f64, f64)>, ChromaticError> {
+    let lenses = lenses_at(lens, wavelengths)?;
+    let refocus = common_refocus(&lenses, refocus);
+
+    let mut image = Vec::with_capacity(lenses.len());
+    for (lens, refocus) in lenses.iter().zip(refocus) {
+        let chief = chief_ray(field, lens)?;
+        let p = trace_ray(&chief, lens, refocus).map_err(FieldError::from)?;
+        image.push(p.pvector);
+    }
+    Ok(image
+        .iter()
+        .map(|p| (p.x - image[0].x, p.y - image[0].y))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lens::tests::bk7_singlet;
+    use crate::lens::{Side, Surface};
+
+    #[test]
+    fn singlet_focal_shift() {
+        // blue focuses short of red in an uncorrected singlet
+        let shift = focal_shift(&bk7_singlet(), &[0.5876, 0.4861, 0.6563]).unwrap();
+        assert_eq!(shift[0], 0.0);
+        assert!(shift[1] < 0.0 && shift[2] > 0.0);
+
+        // a mirror has none
+        let mut mirror = Surface::new(Side::new(-100., 0., vec![]), 0., 1.);
+        mirror.mirror = true;
+        let lens = Lens::new(25., 24., vec![mirror]);
+        let shift = focal_shift(&lens, &[0.5876, 0.4861]).unwrap();
+        assert_eq!(shift, vec![0.0, 0.0]);
+
+        assert_eq!(focal_shift(&lens, &[]), Err(ChromaticError::NoWavelengths));
+    }
+
+    #[test]
+    fn stop_at_lens_lateral_color() {
+        let lens = bk7_singlet();
+        let field = Field::ObjectAngle { x: 0.0, y: 0.05 };
+        let color = lateral_color(&lens, &[0.5876, 0.4861, 0.6563], &field, 0.0).unwrap();
+        assert_eq!(color[0], (0.0, 0.0));
+
+        // with the stop at the lens the blue chief ray bends more
+        assert!(color[1].1.abs() > 0.0);
+        assert!(color[1].1.signum() != color[2].1.signum());
+        assert!(color.iter().all(|c| c.0.abs() < 1e-12));
+    }
+
+    #[test]
+    fn weights_sum_to_one() {
+        assert_eq!(normalized_weights(2, &[]), vec![0.5, 0.5]);
+        assert_eq!(
+            normalized_weights(3, &[2.0, 1.0, 1.0]),
+            vec![0.5, 0.25, 0.25]
+        );
+        assert_eq!(normalized_weights(2, &[0.0, 0.0]), vec![0.5, 0.5]);
+    }
+}
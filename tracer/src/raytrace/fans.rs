@@ -0,0 +1,182 @@
+// Ray aberration curves across the pupil. Pupil positions are on the z = 0 plane in lens units,
+// relative to where the chief ray crosses it. Tangential fans run along y and sagittal fans
+// along x. Transverse aberrations are image plane offsets from the chief ray of the first
+// wavelength, so lateral color shows as an offset of the other fans. Every wavelength lands on
+// the image plane of the first. A ray that fails leaves None at its pupil position.
+use super::{
+    field::{chief_ray, pupil_ray, Field, FieldError},
+    sampling::fan,
+    trace_ray,
+    wfe::{calc_opd_opl, OpdReference},
+};
+use crate::lens::Lens;
+use serde::Serialize;
+
+// on axis transverse (TSA) and longitudinal (LSA) spherical aberration at each pupil height,
+// LSA is where the ray crosses the axis relative to the image plane
+#[derive(Debug, Clone, Serialize)]
+pub struct AxialCurve {
+    pub wavelength: f64,
+    pub tsa: Vec<Option<f64>>,
+    pub lsa: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AxialAberrations {
+    pub height: Vec<f64>,
+    pub curves: Vec<AxialCurve>,
+}
+
+// transverse aberrations ey of the tangential and ex of the sagittal fan, and the OPD fans in
+// waves of the wavelength
+#[derive(Debug, Clone, Serialize)]
+pub struct RayFan {
+    pub wavelength: f64,
+    pub tangential: Vec<Option<f64>>,
+    pub sagittal: Vec<Option<f64>>,
+    pub opd_tangential: Vec<Option<f64>>,
+    pub opd_sagittal: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldFans {
+    pub field: Field,
+    pub pupil: Vec<f64>,
+    pub fans: Vec<RayFan>,
+}
+
+// TSA and LSA of num_points heights from -pupil_radius to pupil_radius at each wavelength,
+// lenses and refocus come from lenses_at and common_refocus
+pub fn axial_aberrations(
+    lenses: &[Lens],
+    refocus: &[f64],
+    wavelengths: &[f64],
+    pupil_radius: f64,
+    num_points: usize,
+) -> Result<AxialAberrations, FieldError> {
+    let chief = chief_ray(&Field::default(), &lenses[0])?;
+    let height = fan(num_points, pupil_radius).collect::<Vec<f64>>();
+
+    let curves = lenses
+        .iter()
+        .zip(refocus)
+        .zip(wavelengths)
+        .map(|((lens, refocus), wavelength)| {
+            let image = height
+                .iter()
+                .map(|y| trace_ray(&pupil_ray(&chief, lens, 0.0, *y), lens, *refocus).ok())
+                .collect::<Vec<_>>();
+            AxialCurve {
+                wavelength: *wavelength,
+                tsa: image.iter().map(|r| Some(r.as_ref()?.pvector.y)).collect(),
+                lsa: image
+                    .iter()
+                    .map(|r| {
+                        let r = r.as_ref()?;
+                        (r.edir.y != 0.0).then(|| -r.pvector.y * r.edir.z / r.edir.y)
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    Ok(AxialAberrations { height, curves })
+}
+
+// tangential, sagittal and OPD fans of the field at each wavelength from num_points pupil
+// positions between -pupil_radius and pupil_radius
+pub fn field_fans(
+    lenses: &[Lens],
+    refocus: &[f64],
+    wavelengths: &[f64],
+    field: &Field,
+    pupil_radius: f64,
+    num_points: usize,
+) -> Result<FieldFans, FieldError> {
+    let chief = chief_ray(field, &lenses[0])?;
+    let center = trace_ray(&chief, &lenses[0], refocus[0])?.pvector;
+    let pupil = fan(num_points, pupil_radius).collect::<Vec<f64>>();
+
+    let mut fans = Vec::with_capacity(lenses.len());
+    for ((lens, refocus), wavelength) in lenses.iter().zip(refocus).zip(wavelengths) {
+        let reference = OpdReference::new(&chief, lens, *refocus)?;
+        let transverse = |x: f64, y: f64| {
+            trace_ray(&pupil_ray(&chief, lens, x, y), lens, *refocus)
+                .ok()
+                .map(|r| (r.pvector.x - center.x, r.pvector.y - center.y))
+        };
+        let opd = |x: f64, y: f64| {
+            let ray = pupil_ray(&chief, lens, x, y);
+            calc_opd_opl(
+                ray.pvector,
+                ray.edir,
+                lens,
+                *wavelength,
+                *refocus,
+                &reference,
+            )
+            .ok()
+        };
+        fans.push(RayFan {
+            wavelength: *wavelength,
+            tangential: pupil.iter().map(|y| Some(transverse(0.0, *y)?.1)).collect(),
+            sagittal: pupil.iter().map(|x| Some(transverse(*x, 0.0)?.0)).collect(),
+            opd_tangential: pupil.iter().map(|y| opd(0.0, *y)).collect(),
+            opd_sagittal: pupil.iter().map(|x| opd(*x, 0.0)).collect(),
+        });
+    }
+    Ok(FieldFans {
+        field: *field,
+        pupil,
+        fans,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lens::tests::bk7_singlet;
+    use crate::raytrace::chromatic::{common_refocus, lenses_at};
+
+    #[test]
+    fn singlet_spherical_aberration() {
+        let wavelengths = [0.5876, 0.4861];
+        let lenses = lenses_at(&bk7_singlet(), &wavelengths).unwrap();
+        let refocus = common_refocus(&lenses, 0.0);
+        let axial = axial_aberrations(&lenses, &refocus, &wavelengths, 10.0, 5).unwrap();
+        assert_eq!(axial.height, vec![-10.0, -5.0, 0.0, 5.0, 10.0]);
+
+        // undercorrected: the edge crosses the axis short of the paraxial focus and lands on
+        // the far side of it, more so than the zone
+        let d = &axial.curves[0];
+        assert_eq!(d.lsa[2], None);
+        let (zone, edge) = (d.lsa[3].unwrap(), d.lsa[4].unwrap());
+        assert!(edge < zone && zone < 0.0);
+        assert!(d.tsa[4].unwrap() < 0.0);
+        assert!((d.tsa[0].unwrap() + d.tsa[4].unwrap()).abs() < 1e-12);
+
+        // blue focuses short of d
+        assert!(axial.curves[1].lsa[3].unwrap() < zone);
+    }
+
+    #[test]
+    fn fans_are_zero_at_the_chief_ray() {
+        let wavelengths = [0.5876];
+        let lenses = lenses_at(&bk7_singlet(), &wavelengths).unwrap();
+        let refocus = common_refocus(&lenses, 0.0);
+        let field = Field::ObjectAngle { x: 0.0, y: 0.05 };
+        let fans = field_fans(&lenses, &refocus, &wavelengths, &field, 10.0, 5).unwrap();
+        let fan = &fans.fans[0];
+
+        assert!(fan.tangential[2].unwrap().abs() < 1e-12);
+        assert!(fan.sagittal[2].unwrap().abs() < 1e-12);
+        assert!(fan.opd_tangential[2].unwrap().abs() < 1e-6);
+
+        // off axis the tangential fan is lopsided (coma), the sagittal fan stays odd in x
+        let t = &fan.tangential;
+        assert!((t[0].unwrap() + t[4].unwrap()).abs() > 1e-6);
+        let s = &fan.sagittal;
+        assert!((s[0].unwrap() + s[4].unwrap()).abs() < 1e-9);
+        let o = &fan.opd_sagittal;
+        assert!((o[0].unwrap() - o[4].unwrap()).abs() < 1e-6);
+    }
+}
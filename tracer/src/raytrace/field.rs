@@ -0,0 +1,373 @@
+// Field points and the chief and marginal rays that define them. For an object at infinity
+// rays are launched from the z = 0 plane and a field angle tilts the collimated beam. A finite
+// object launches rays from the object plane toward points on z = 0. The chief ray of a field
+// is aimed at the center of the aperture stop (the first surface when the lens has no stop).
+use super::{
+    ray_at_surface,
+    ray_vector::{Ray, Vector3D, CPROPV},
+    trace_ray, translate_to_flat, TraceError,
+};
+use crate::lens::Lens;
+use crate::paraxial::stop_surface;
+use serde::{Deserialize, Serialize};
+
+const AIM_TOLERANCE: f64 = 1e-10;
+const AIM_ITERATIONS: usize = 20;
+
+// Object angles are in radians, heights in lens units. Image heights are measured on the
+// paraxial image plane. An object angle with a finite object places the object point where a
+// ray at that angle through the first vertex leaves the object plane.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Field {
+    ObjectAngle { x: f64, y: f64 },
+    ObjectHeight { x: f64, y: f64 },
+    ImageHeight { x: f64, y: f64 },
+}
+
+impl Default for Field {
+    fn default() -> Self {
+        Field::ObjectAngle { x: 0.0, y: 0.0 }
+    }
+}
+
+impl Field {
+    // the same kind of field at a fraction of its size
+    pub fn scaled(&self, fraction: f64) -> Field {
+        match *self {
+            Field::ObjectAngle { x, y } => Field::ObjectAngle {
+                x: x * fraction,
+                y: y * fraction,
+            },
+            Field::ObjectHeight { x, y } => Field::ObjectHeight {
+                x: x * fraction,
+                y: y * fraction,
+            },
+            Field::ImageHeight { x, y } => Field::ImageHeight {
+                x: x * fraction,
+                y: y * fraction,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldError {
+    InfiniteObject, // object heights need a finite object distance
+    NoStopAperture, // the stop has no clear aperture to aim the marginal ray at
+    NonConvergent,  // ray aiming did not settle
+    Trace(TraceError),
+}
+
+impl From<TraceError> for FieldError {
+    fn from(err: TraceError) -> Self {
+        FieldError::Trace(err)
+    }
+}
+
+// beam direction for field angles, the y angle tilts the beam toward +y and the x angle then
+// swings it about the y axis
+pub fn field_direction(ax: f64, ay: f64) -> Vector3D {
+    CPROPV.rotate_x(-ay).rotate_y(ax)
+}
+
+// ray from an object point toward (x, y) on the z = 0 plane, travelling toward +z so it
+// diverges from a real object and converges on a virtual one
+pub fn ray_from_object(object: &Vector3D, x: f64, y: f64) -> Ray {
+    let to_pupil = Vector3D {
+        x: x - object.x,
+        y: y - object.y,
+        z: -object.z,
+    };
+    Ray {
+        pvector: object.clone(),
+        edir: &to_pupil * (to_pupil.z.signum() / to_pupil.length()),
+    }
+}
+
+// Move a ray generated for the on axis field onto the field of the chief ray. Collimated rays
+// are rotated onto the chief ray direction and shifted with its launch point, rays from a
+// finite object are shifted with the chief ray at both the object and the z = 0 plane.
+pub fn orient_to_chief(ray: &Ray, chief: &Ray, lens: &Lens) -> Ray {
+    let shift = Vector3D {
+        x: chief.pvector.x,
+        y: chief.pvector.y,
+        z: 0.0,
+    };
+    match lens.object_plane() {
+        None => {
+            let ay = chief.edir.y.asin();
+            let ax = chief.edir.x.atan2(chief.edir.z);
+            Ray {
+                pvector: &ray.pvector + &shift,
+                edir: ray.edir.rotate_x(-ay).rotate_y(ax),
+            }
+        }
+        Some(_) => {
+            let pupil = translate_to_flat(&ray.pvector, &ray.edir, 0.0);
+            let center = translate_to_flat(&chief.pvector, &chief.edir, 0.0);
+            ray_from_object(
+                &(&ray.pvector + &shift),
+                pupil.x + center.x,
+                pupil.y + center.y,
+            )
+        }
+    }
+}
+
+// ray through the point (px, py) of the z = 0 plane relative to where the chief ray crosses it
+pub fn pupil_ray(chief: &Ray, lens: &Lens, px: f64, py: f64) -> Ray {
+    match lens.object_plane() {
+        None => Ray {
+            pvector: Vector3D {
+                x: chief.pvector.x + px,
+                y: chief.pvector.y + py,
+                z: chief.pvector.z,
+            },
+            edir: chief.edir.clone(),
+        },
+        Some(_) => {
+            let center = translate_to_flat(&chief.pvector, &chief.edir, 0.0);
+            ray_from_object(&chief.pvector, center.x + px, center.y + py)
+        }
+    }
+}
+
+// launch ray of the field through the center of the stop
+pub fn chief_ray(field: &Field, lens: &Lens) -> Result<Ray, FieldError> {
+    match (*field, lens.object_plane()) {
+        (Field::ObjectAngle { x, y }, None) => aim_collimated(&field_direction(x, y), lens),
+        (Field::ObjectAngle { x, y }, Some(z)) => aim_from_object(
+            &Vector3D {
+                x: z * x.tan(),
+                y: z * y.tan(),
+                z,
+            },
+            lens,
+        ),
+        (Field::ObjectHeight { .. }, None) => Err(FieldError::InfiniteObject),
+        (Field::ObjectHeight { x, y }, Some(z)) => aim_from_object(&Vector3D { x, y, z }, lens),
+        (Field::ImageHeight { x, y }, _) => aim_image_height(x, y, lens),
+    }
+}
+
+// launch ray of the field through the +y edge of the stop
+pub fn marginal_ray(field: &Field, lens: &Lens) -> Result<Ray, FieldError> {
+    let chief = chief_ray(field, lens)?;
+    let radius = lens
+        .clear_radius(stop_surface(lens))
+        .ok_or(FieldError::NoStopAperture)?;
+    match lens.object_plane() {
+        None => aim(lens, (0.0, radius), |x, y| Ray {
+            pvector: Vector3D { x, y, z: 0.0 },
+            edir: chief.edir.clone(),
+        }),
+        Some(_) => aim(lens, (0.0, radius), |x, y| {
+            ray_from_object(&chief.pvector, x, y)
+        }),
+    }
+}
+
+fn aim_collimated(dir: &Vector3D, lens: &Lens) -> Result<Ray, FieldError> {
+    aim(lens, (0.0, 0.0), |x, y| Ray {
+        pvector: Vector3D { x, y, z: 0.0 },
+        edir: dir.clone(),
+    })
+}
+
+fn aim_from_object(object: &Vector3D, lens: &Lens) -> Result<Ray, FieldError> {
+    aim(lens, (0.0, 0.0), |x, y| ray_from_object(object, x, y))
+}
+
+// Point (x, y) on z = 0 whose launch ray meets the stop at target, by Newton's method with a
+// finite difference Jacobian.
+fn aim(
+    lens: &Lens,
+    target: (f64, f64),
+    launch: impl Fn(f64, f64) -> Ray,
+) -> Result<Ray, FieldError> {
+    let stop = stop_surface(lens);
+    let miss = |x: f64, y: f64| -> Result<(f64, f64), FieldError> {
+        let p = ray_at_surface(&launch(x, y), lens, stop)?;
+        Ok((p.x - target.0, p.y - target.1))
+    };
+
+    let h = 1e-6;
+    let (mut x, mut y) = target;
+    for _ in 0..AIM_ITERATIONS {
+        let (fx, fy) = miss(x, y)?;
+        if fx.hypot(fy) < AIM_TOLERANCE {
+            return Ok(launch(x, y));
+        }
+        let (fx_x, fy_x) = miss(x + h, y)?;
+        let (fx_y, fy_y) = miss(x, y + h)?;
+        let (dx, dy) = solve_2x2(
+            [
+                [(fx_x - fx) / h, (fx_y - fx) / h],
+                [(fy_x - fy) / h, (fy_y - fy) / h],
+            ],
+            (fx, fy),
+        )
+        .ok_or(FieldError::NonConvergent)?;
+        x -= dx;
+        y -= dy;
+    }
+    Err(FieldError::NonConvergent)
+}
+
+// Chief ray that lands at the image height. The search runs over the field angle for an object
+// at infinity and over the object height otherwise, starting from the paraxial field.
+fn aim_image_height(hx: f64, hy: f64, lens: &Lens) -> Result<Ray, FieldError> {
+    let landing = |u: f64, v: f64| -> Result<(f64, f64, Ray), FieldError> {
+        let chief = match lens.object_plane() {
+            None => aim_collimated(&field_direction(u, v), lens)?,
+            Some(z) => aim_from_object(&Vector3D { x: u, y: v, z }, lens)?,
+        };
+        let image = trace_ray(&chief, lens, 0.0)?;
+        Ok((image.pvector.x - hx, image.pvector.y - hy, chief))
+    };
+
+    let (mut ax, mut ay, h) = match lens.object_plane() {
+        None => {
+            let efl = lens.efl();
+            ((hx / efl).atan(), (hy / efl).atan(), 1e-7)
+        }
+        Some(_) => {
+            let m = lens.magnification();
+            (hx / m, hy / m, 1e-6)
+        }
+    };
+    for _ in 0..AIM_ITERATIONS {
+        let (fx, fy, chief) = landing(ax, ay)?;
+        if fx.hypot(fy) < AIM_TOLERANCE {
+            return Ok(chief);
+        }
+        let (fx_x, fy_x, _) = landing(ax + h, ay)?;
+        let (fx_y, fy_y, _) = landing(ax, ay + h)?;
+        let (dx, dy) = solve_2x2(
+            [
+                [(fx_x - fx) / h, (fx_y - fx) / h],
+                [(fy_x - fy) / h, (fy_y - fy) / h],
+            ],
+            (fx, fy),
+        )
+        .ok_or(FieldError::NonConvergent)?;
+        ax -= dx;
+        ay -= dy;
+    }
+    Err(FieldError::NonConvergent)
+}
+
+fn solve_2x2(j: [[f64; 2]; 2], f: (f64, f64)) -> Option<(f64, f64)> {
+    let det = j[0][0] * j[1][1] - j[0][1] * j[1][0];
+    if det.abs() < 1e-14 {
+        return None;
+    }
+    Some((
+        (j[1][1] * f.0 - j[0][1] * f.1) / det,
+        (j[0][0] * f.1 - j[1][0] * f.0) / det,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lens::{Side, Surface};
+
+    // stop in air 10 behind the first element of a two singlet system
+    fn stopped_doublet() -> Lens {
+        let mut stop = Surface::new(Side::new(0.0, 0.0, vec![]), 10.0, 1.0);
+        stop.clear_ap = Some(8.0);
+        let mut lens = Lens::new(
+            25.0,
+            24.0,
+            vec![
+                Surface::new(Side::new(60.0, 0.0, vec![]), 4.0, 1.6),
+                Surface::new(Side::new(0.0, 0.0, vec![]), 10.0, 1.0),
+                stop,
+                Surface::new(Side::new(50.0, 0.0, vec![]), 4.0, 1.5),
+                Surface::new(Side::new(-80.0, 0.0, vec![]), 0.0, 1.0),
+            ],
+        );
+        lens.stop = Some(2);
+        lens
+    }
+
+    #[test]
+    fn chief_ray_passes_stop_center() {
+        let lens = stopped_doublet();
+        let field = Field::ObjectAngle { x: 0.03, y: -0.05 };
+        let chief = chief_ray(&field, &lens).unwrap();
+        let at_stop = ray_at_surface(&chief, &lens, 2).unwrap();
+
+        assert!(at_stop.x.hypot(at_stop.y) < 1e-9);
+        assert!((chief.edir.clone() - field_direction(0.03, -0.05)).length() < 1e-15);
+
+        let marginal = marginal_ray(&field, &lens).unwrap();
+        let at_stop = ray_at_surface(&marginal, &lens, 2).unwrap();
+        assert!(at_stop.x.abs() < 1e-9 && (at_stop.y - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn image_height_field_lands() {
+        let lens = stopped_doublet();
+        let chief = chief_ray(&Field::ImageHeight { x: 0.0, y: 1.5 }, &lens).unwrap();
+        let image = trace_ray(&chief, &lens, 0.0).unwrap();
+
+        assert!(image.pvector.x.abs() < 1e-9);
+        assert!((image.pvector.y - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn orient_matches_field_direction() {
+        let chief = Ray {
+            pvector: Vector3D {
+                x: 1.0,
+                y: -2.0,
+                z: 0.0,
+            },
+            edir: field_direction(-0.2, 0.1),
+        };
+        let axial = Ray {
+            pvector: Vector3D {
+                x: 0.5,
+                y: 0.0,
+                z: 0.0,
+            },
+            edir: CPROPV,
+        };
+        let oriented = orient_to_chief(&axial, &chief, &stopped_doublet());
+
+        assert!((oriented.edir - chief.edir).length() < 1e-15);
+        assert_eq!(oriented.pvector.x, 1.5);
+        assert_eq!(
+            chief_ray(&Field::ObjectHeight { x: 0.0, y: 1.0 }, &stopped_doublet()).unwrap_err(),
+            FieldError::InfiniteObject
+        );
+    }
+
+    #[test]
+    fn finite_object_fields() {
+        let mut lens = stopped_doublet();
+        lens.object_distance = Some(80.0);
+
+        let field = Field::ObjectHeight { x: 0.5, y: -2.0 };
+        let chief = chief_ray(&field, &lens).unwrap();
+        assert_eq!(chief.pvector.z, -80.0);
+        let at_stop = ray_at_surface(&chief, &lens, 2).unwrap();
+        assert!(at_stop.x.hypot(at_stop.y) < 1e-9);
+
+        let marginal = marginal_ray(&field, &lens).unwrap();
+        assert_eq!(marginal.pvector, chief.pvector);
+        let at_stop = ray_at_surface(&marginal, &lens, 2).unwrap();
+        assert!((at_stop.y - 4.0).abs() < 1e-9);
+
+        let chief = chief_ray(&Field::ImageHeight { x: 0.0, y: 1.5 }, &lens).unwrap();
+        let image = trace_ray(&chief, &lens, 0.0).unwrap();
+        assert!((image.pvector.y - 1.5).abs() < 1e-9);
+
+        // a virtual object, the launch rays converge toward it
+        lens.object_distance = Some(-200.0);
+        let chief = chief_ray(&Field::ObjectHeight { x: 0.0, y: 3.0 }, &lens).unwrap();
+        assert!(chief.edir.z > 0.0 && chief.edir.y > 0.0);
+    }
+}
@@ -0,0 +1,142 @@
+// Freeform sag terms added on top of the conic base of a side.
+// XY polynomial: coeffs run through (x/R)^i (y/R)^j by increasing degree i + j, starting at
+// degree 1: x, y, x^2, xy, y^2, x^3, x^2 y, ...
+// Zernike: coeffs[j] multiplies the Noll ordered (j + 1), normalized Zernike term over R.
+
+// sum of the XY polynomial terms
+pub fn xy_poly_sag(x: f64, y: f64, coeffs: &[f64], norm_radius: f64) -> f64 {
+    let (u, v) = (x / norm_radius, y / norm_radius);
+    xy_terms(coeffs.len())
+        .zip(coeffs.iter())
+        .map(|((i, j), a)| a * u.powi(i) * v.powi(j))
+        .sum()
+}
+
+// (dz/dx, dz/dy) of the XY polynomial terms
+pub fn xy_poly_slope(x: f64, y: f64, coeffs: &[f64], norm_radius: f64) -> (f64, f64) {
+    let (u, v) = (x / norm_radius, y / norm_radius);
+    let mut dzdx = 0.0;
+    let mut dzdy = 0.0;
+
+    for ((i, j), a) in xy_terms(coeffs.len()).zip(coeffs.iter()) {
+        if i > 0 {
+            dzdx += a * i as f64 * u.powi(i - 1) * v.powi(j);
+        }
+        if j > 0 {
+            dzdy += a * j as f64 * u.powi(i) * v.powi(j - 1);
+        }
+    }
+    (dzdx / norm_radius, dzdy / norm_radius)
+}
+
+// exponents (i, j) of the first n XY polynomial terms
+fn xy_terms(n: usize) -> impl Iterator<Item = (i32, i32)> {
+    (1..)
+        .flat_map(|degree: i32| (0..=degree).map(move |j| (degree - j, j)))
+        .take(n)
+}
+
+// sum of the Zernike terms
+pub fn zernike_sag(x: f64, y: f64, coeffs: &[f64], norm_radius: f64) -> f64 {
+    let rho = (x * x + y * y).sqrt() / norm_radius;
+    let theta = y.atan2(x);
+
+    coeffs
+        .iter()
+        .enumerate()
+        .map(|(j, a)| a * zernike(j + 1, rho, theta))
+        .sum()
+}
+
+// (dz/dx, dz/dy) of the Zernike terms by central differences
+pub fn zernike_slope(x: f64, y: f64, coeffs: &[f64], norm_radius: f64) -> (f64, f64) {
+    let h = 1e-6 * norm_radius;
+    (
+        (zernike_sag(x + h, y, coeffs, norm_radius) - zernike_sag(x - h, y, coeffs, norm_radius))
+            / (2.0 * h),
+        (zernike_sag(x, y + h, coeffs, norm_radius) - zernike_sag(x, y - h, coeffs, norm_radius))
+            / (2.0 * h),
+    )
+}
+
+// normalized Zernike polynomial for the 1 based Noll index
+pub fn zernike(noll: usize, rho: f64, theta: f64) -> f64 {
+    let (n, m) = noll_to_nm(noll);
+    let radial = (n as f64 + 1.0).sqrt() * zernike_radial(n, m.unsigned_abs() as usize, rho);
+
+    if m > 0 {
+        2.0_f64.sqrt() * radial * (m as f64 * theta).cos()
+    } else if m < 0 {
+        2.0_f64.sqrt() * radial * (-m as f64 * theta).sin()
+    } else {
+        radial
+    }
+}
+
+// radial order n and azimuthal frequency m, negative m are the sine terms
+fn noll_to_nm(noll: usize) -> (usize, i32) {
+    let mut n = 0;
+    let mut j1 = noll - 1;
+    while j1 > n {
+        n += 1;
+        j1 -= n;
+    }
+    let sign = if noll % 2 == 0 { 1 } else { -1 };
+    let m = sign * ((n % 2) + 2 * ((j1 + (n + 1) % 2) / 2)) as i32;
+    (n, m)
+}
+
+fn zernike_radial(n: usize, m: usize, rho: f64) -> f64 {
+    let fact = |k: usize| (1..=k).fold(1.0, |acc, i| acc * i as f64);
+    (0..=(n - m) / 2)
+        .map(|k| {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            sign * fact(n - k) / (fact(k) * fact((n + m) / 2 - k) * fact((n - m) / 2 - k))
+                * rho.powi((n - 2 * k) as i32)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noll_ordering() {
+        let nm = (1..=11).map(noll_to_nm).collect::<Vec<(usize, i32)>>();
+        assert_eq!(
+            nm,
+            vec![
+                (0, 0),
+                (1, 1),
+                (1, -1),
+                (2, 0),
+                (2, -2),
+                (2, 2),
+                (3, -1),
+                (3, 1),
+                (3, -3),
+                (3, 3),
+                (4, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn zernike_low_orders() {
+        let (rho, theta): (f64, f64) = (0.7, 0.4);
+        let defocus = 3.0_f64.sqrt() * (2.0 * rho * rho - 1.0);
+        let coma = 8.0_f64.sqrt() * (3.0 * rho.powi(3) - 2.0 * rho) * theta.cos();
+
+        assert!((zernike(4, rho, theta) - defocus).abs() < 1e-12);
+        assert!((zernike(8, rho, theta) - coma).abs() < 1e-12);
+    }
+
+    #[test]
+    fn xy_term_order() {
+        // x, y, x^2, xy, y^2
+        let (x, y) = (2.0, 3.0);
+        assert_eq!(xy_poly_sag(x, y, &[0.0, 0.0, 0.0, 1.0], 1.0), x * y);
+        assert_eq!(xy_poly_sag(x, y, &[0.0, 0.0, 0.0, 0.0, 1.0], 1.0), y * y);
+    }
+}
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use std::ops;
 
 pub const CPROPV: Vector3D = Vector3D {
@@ -6,7 +7,7 @@ pub const CPROPV: Vector3D = Vector3D {
     z: 1.0,
 };
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Serialize)]
 #[repr(C)]
 pub struct Ray {
     pub pvector: Vector3D,
@@ -14,15 +15,17 @@ pub struct Ray {
 }
 
 impl Ray {
+    // angle to the optical axis and the axial crossing distance, rays travelling toward -z
+    // after a mirror are measured against -z
     pub fn calc_aoi_lsa(&self) -> (f64, f64) {
-        let aoi = self.edir.dot_product(&CPROPV).acos();
+        let aoi = self.edir.z.abs().acos();
         let lsa = -1.0 * (self.pvector.x.powi(2) + self.pvector.y.powi(2)).sqrt() / aoi.tan();
         (aoi, lsa)
     }
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Vector3D {
     pub x: f64,
     pub y: f64,
@@ -41,6 +44,34 @@ impl Vector3D {
     pub fn dot_product(&self, other: &Vector3D) -> f64 {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
+
+    // right handed rotations about the coordinate axes, angles in radians
+    pub fn rotate_x(&self, angle: f64) -> Vector3D {
+        let (s, c) = angle.sin_cos();
+        Vector3D {
+            x: self.x,
+            y: c * self.y - s * self.z,
+            z: s * self.y + c * self.z,
+        }
+    }
+
+    pub fn rotate_y(&self, angle: f64) -> Vector3D {
+        let (s, c) = angle.sin_cos();
+        Vector3D {
+            x: c * self.x + s * self.z,
+            y: self.y,
+            z: -s * self.x + c * self.z,
+        }
+    }
+
+    pub fn rotate_z(&self, angle: f64) -> Vector3D {
+        let (s, c) = angle.sin_cos();
+        Vector3D {
+            x: c * self.x - s * self.y,
+            y: s * self.x + c * self.y,
+            z: self.z,
+        }
+    }
 }
 
 // use this crates macro to more easily implement all the operator overloads.
@@ -201,6 +232,28 @@ mod tests {
         assert_eq!(a.dot_product(&b), 6.0)
     }
 
+    #[test]
+    fn rotate_vectors() {
+        let a = Vector3D {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        };
+        let b = a
+            .rotate_x(0.3)
+            .rotate_y(-0.7)
+            .rotate_z(1.1)
+            .rotate_z(-1.1)
+            .rotate_y(0.7)
+            .rotate_x(-0.3);
+
+        assert!((a - b).length() < 1e-12);
+        assert_eq!(
+            format!("{:.4}", CPROPV.rotate_x(std::f64::consts::FRAC_PI_2).y),
+            "-1.0000"
+        );
+    }
+
     #[test]
     fn vector_length() {
         let a = Vector3D {
@@ -0,0 +1,278 @@
+// Point patterns on a disc, used for ray positions in the pupil and for directions or object
+// points across an extended source. count is the number of points asked for, the regular
+// patterns return the nearest count their geometry allows.
+// Random: uniform rejection sampling, the original gen_random_rays behavior.
+// Fibonacci: golden angle spiral, as generateFibonacciRays in raytrace.ts.
+// Hexapolar: the center and rings of 6, 12, 18, ... points.
+// SquareGrid: a square grid clipped to the disc, as generateCollimatedRayBundle.
+// Fan: points along the y axis.
+// CrossFan: points along both axes sharing the center, 4 k + 1 of them.
+// CircleGrid: a y fan and 20 points on the rim as generateCircleGrid, with fewer rim points
+// below 40 so the total stays count.
+// Sobol, Halton: low discrepancy sequences mapped to the disc, successive calls continue the
+// sequence.
+// Gaussian: random points with a Gaussian intensity density of 1/e^2 radius e2_radius,
+// truncated at the disc.
+use rand::Rng;
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub enum Sampling {
+    #[default]
+    Random,
+    Fibonacci,
+    Hexapolar,
+    SquareGrid,
+    Fan,
+    CrossFan,
+    CircleGrid,
+    Sobol,
+    Halton,
+    Gaussian {
+        e2_radius: f64,
+    },
+}
+
+// sampling of the source ray positions (pupil) and of their directions or object points
+// (source)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RaySampling {
+    pub pupil: Sampling,
+    pub source: Sampling,
+}
+
+// A pattern and its position in a quasi random sequence
+pub struct DiscSampler {
+    sampling: Sampling,
+    index: u32,
+}
+
+impl DiscSampler {
+    pub fn new(sampling: Sampling) -> Self {
+        DiscSampler { sampling, index: 0 }
+    }
+
+    pub fn points(&mut self, count: usize, radius: f64, rng: &mut impl Rng) -> Vec<(f64, f64)> {
+        match self.sampling {
+            Sampling::Random => (0..count)
+                .map(|_| loop {
+                    let x = rng.gen_range(-radius..radius);
+                    let y = rng.gen_range(-radius..radius);
+                    if x * x + y * y <= radius * radius {
+                        break (x, y);
+                    }
+                })
+                .collect(),
+            Sampling::Fibonacci => {
+                let golden = (1.0 + 5f64.sqrt()) / 2.0;
+                (0..count)
+                    .map(|i| polar(radius, i as f64 / count as f64, (i as f64 / golden).fract()))
+                    .collect()
+            }
+            Sampling::Hexapolar => hexapolar(count, radius),
+            Sampling::SquareGrid => square_grid(count, radius),
+            Sampling::Fan => fan(count, radius).map(|y| (0.0, y)).collect(),
+            Sampling::CrossFan => {
+                // 2 k + 1 points on each axis, the x axis skips the center
+                let k = ((count.max(1) - 1) as f64 / 4.0).round() as usize;
+                let axis = fan(2 * k + 1, radius).collect::<Vec<f64>>();
+                axis.iter()
+                    .map(|y| (0.0, *y))
+                    .chain(
+                        axis.iter()
+                            .enumerate()
+                            .filter(|(i, _)| *i != k)
+                            .map(|(_, x)| (*x, 0.0)),
+                    )
+                    .collect()
+            }
+            Sampling::CircleGrid => {
+                let rim = 20.min(count / 2);
+                fan(count - rim, radius)
+                    .map(|y| (0.0, y))
+                    .chain((0..rim).map(|i| {
+                        let theta = 2.0 * PI * i as f64 / rim as f64;
+                        (radius * theta.cos(), radius * theta.sin())
+                    }))
+                    .collect()
+            }
+            Sampling::Sobol | Sampling::Halton => (0..count)
+                .map(|_| {
+                    let (u, v) = match self.sampling {
+                        Sampling::Sobol => sobol(self.index),
+                        _ => (
+                            radical_inverse(self.index + 1, 2),
+                            radical_inverse(self.index + 1, 3),
+                        ),
+                    };
+                    self.index = self.index.wrapping_add(1);
+                    polar(radius, u, v)
+                })
+                .collect(),
+            Sampling::Gaussian { e2_radius } => {
+                // r^2 of the truncated density r exp(-2 r^2 / w^2) by inverting its CDF
+                let w2 = e2_radius * e2_radius;
+                let tail = 1.0 - (-2.0 * radius * radius / w2).exp();
+                (0..count)
+                    .map(|_| {
+                        let u: f64 = rng.gen();
+                        let r = (-w2 / 2.0 * (1.0 - u * tail).ln()).sqrt();
+                        let theta = rng.gen_range(0.0..2.0 * PI);
+                        (r * theta.cos(), r * theta.sin())
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// area preserving map of the unit square to the disc, u picks the radius and v the angle
+fn polar(radius: f64, u: f64, v: f64) -> (f64, f64) {
+    let r = radius * u.sqrt();
+    let theta = 2.0 * PI * v;
+    (r * theta.cos(), r * theta.sin())
+}
+
+// count points from -radius to radius, the center alone for a single point
+pub fn fan(count: usize, radius: f64) -> impl Iterator<Item = f64> {
+    let step = if count > 1 {
+        2.0 * radius / (count - 1) as f64
+    } else {
+        0.0
+    };
+    let start = if count > 1 { -radius } else { 0.0 };
+    (0..count).map(move |i| start + i as f64 * step)
+}
+
+// the rings whose 1 + 3 k (k + 1) points come nearest count
+fn hexapolar(count: usize, radius: f64) -> Vec<(f64, f64)> {
+    let num_points = |rings: usize| 1 + 3 * rings * (rings + 1);
+    let mut rings = 0;
+    while num_points(rings) < count {
+        rings += 1;
+    }
+    if rings > 0 && count - num_points(rings - 1) < num_points(rings) - count {
+        rings -= 1;
+    }
+    let mut points = vec![(0.0, 0.0)];
+    for k in 1..=rings {
+        let r = radius * k as f64 / rings as f64;
+        for j in 0..6 * k {
+            let theta = 2.0 * PI * j as f64 / (6 * k) as f64;
+            points.push((r * theta.cos(), r * theta.sin()));
+        }
+    }
+    points
+}
+
+// n by n grid across the diameter with n^2 pi / 4 close to count
+fn square_grid(count: usize, radius: f64) -> Vec<(f64, f64)> {
+    let n = (4.0 * count as f64 / PI).sqrt().ceil() as usize;
+    let limit = radius * radius * (1.0 + 1e-9);
+    let axis = fan(n, radius).collect::<Vec<f64>>();
+    axis.iter()
+        .flat_map(|x| axis.iter().map(move |y| (*x, *y)))
+        .filter(|(x, y)| x * x + y * y <= limit)
+        .collect()
+}
+
+// the first two dimensions of the Sobol sequence, van der Corput in base 2 and the x + 1
+// primitive polynomial
+fn sobol(index: u32) -> (f64, f64) {
+    let (mut x, mut y) = (0u32, 0u32);
+    let mut v = 1u32 << 31;
+    let mut bits = index;
+    let mut bit = 0;
+    while bits != 0 {
+        if bits & 1 == 1 {
+            x ^= 1 << (31 - bit);
+            y ^= v;
+        }
+        v ^= v >> 1;
+        bits >>= 1;
+        bit += 1;
+    }
+    let scale = 1.0 / (1u64 << 32) as f64;
+    (x as f64 * scale, y as f64 * scale)
+}
+
+fn radical_inverse(mut index: u32, base: u32) -> f64 {
+    let mut inverse = 0.0;
+    let mut digit = 1.0 / base as f64;
+    while index > 0 {
+        inverse += (index % base) as f64 * digit;
+        index /= base;
+        digit /= base as f64;
+    }
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytrace::source_rng;
+
+    #[test]
+    fn patterns_stay_in_disc() {
+        let mut rng = source_rng(Some(1));
+        let patterns = [
+            Sampling::Random,
+            Sampling::Fibonacci,
+            Sampling::Hexapolar,
+            Sampling::SquareGrid,
+            Sampling::Fan,
+            Sampling::CrossFan,
+            Sampling::CircleGrid,
+            Sampling::Sobol,
+            Sampling::Halton,
+            Sampling::Gaussian { e2_radius: 1.5 },
+        ];
+        for sampling in patterns {
+            let points = DiscSampler::new(sampling).points(100, 2.0, &mut rng);
+            assert!(!points.is_empty(), "{:?}", sampling);
+            assert!(
+                points.iter().all(|(x, y)| x.hypot(*y) <= 2.0 + 1e-12),
+                "{:?}",
+                sampling
+            );
+        }
+
+        // 1 + 6 + 12 + 18 + 24 + 30 = 91 is nearer 100 than the 127 of another ring
+        assert_eq!(hexapolar(100, 1.0).len(), 91);
+        assert_eq!(hexapolar(110, 1.0).len(), 127);
+        assert_eq!(hexapolar(1, 1.0), vec![(0.0, 0.0)]);
+        assert_eq!(fan(3, 1.0).collect::<Vec<f64>>(), vec![-1.0, 0.0, 1.0]);
+
+        // the crosses nearest count, and count points on the circle grid
+        let mut num_points =
+            |sampling, n| DiscSampler::new(sampling).points(n, 1.0, &mut rng).len();
+        assert_eq!(num_points(Sampling::CrossFan, 100), 101);
+        assert_eq!(num_points(Sampling::CrossFan, 5), 5);
+        assert_eq!(num_points(Sampling::CrossFan, 1), 1);
+        assert_eq!(num_points(Sampling::CircleGrid, 100), 100);
+        assert_eq!(num_points(Sampling::CircleGrid, 10), 10);
+        let cross = DiscSampler::new(Sampling::CrossFan).points(9, 1.0, &mut rng);
+        assert_eq!(cross.iter().filter(|p| **p == (0.0, 0.0)).count(), 1);
+    }
+
+    #[test]
+    fn sequences_continue_and_fill_evenly() {
+        assert_eq!(sobol(1), (0.5, 0.5));
+        assert_eq!(sobol(2), (0.25, 0.75));
+        assert_eq!(sobol(3), (0.75, 0.25));
+        assert_eq!(radical_inverse(3, 3), 1.0 / 9.0);
+
+        let mut rng = source_rng(Some(1));
+        let mut sampler = DiscSampler::new(Sampling::Halton);
+        let first = sampler.points(4, 1.0, &mut rng);
+        let next = sampler.points(4, 1.0, &mut rng);
+        assert_ne!(first, next);
+
+        // a quarter of the points in each quadrant, well within random scatter
+        let points = DiscSampler::new(Sampling::Sobol).points(1024, 1.0, &mut rng);
+        let upper_right = points.iter().filter(|(x, y)| *x > 0.0 && *y > 0.0).count();
+        assert!((upper_right as i32 - 256).abs() <= 4, "{}", upper_right);
+    }
+}
@@ -0,0 +1,226 @@
+// Spot diagram statistics. Spot positions are on the image plane in lens units. The radii are
+// measured from the centroid of the spot, and the Airy radius of the traced beam is given with
+// them for comparison. In a spectrum every wavelength lands on the image plane of the first.
+use super::{
+    field::{chief_ray, pupil_ray, Field, FieldError},
+    ray_vector::Ray,
+    trace_ray,
+};
+use crate::lens::Lens;
+use crate::paraxial::first_order;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SpotStats {
+    pub centroid_x: f64,
+    pub centroid_y: f64,
+    pub rms_radius: f64,
+    pub geo_radius: f64, // largest distance from the centroid
+    pub rms_x: f64,
+    pub rms_y: f64,
+    pub num_rays: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WavelengthSpot {
+    pub wavelength: f64,
+    pub airy_radius: f64,
+    pub stats: Option<SpotStats>, // None when every ray failed
+    pub points: Vec<(f64, f64)>,
+    pub num_failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldSpot {
+    pub field: Field,
+    pub wavelengths: Vec<WavelengthSpot>,
+    // all wavelengths together, each carrying its spectral weight
+    pub polychromatic: Option<SpotStats>,
+}
+
+// weighted statistics of (x, y, weight) points, None without points
+pub fn spot_stats(points: &[(f64, f64, f64)]) -> Option<SpotStats> {
+    let total: f64 = points.iter().map(|p| p.2).sum();
+    if points.is_empty() || total <= 0.0 {
+        return None;
+    }
+    let cx = points.iter().map(|(x, _, w)| x * w).sum::<f64>() / total;
+    let cy = points.iter().map(|(_, y, w)| y * w).sum::<f64>() / total;
+    let var_x = points
+        .iter()
+        .map(|(x, _, w)| w * (x - cx).powi(2))
+        .sum::<f64>()
+        / total;
+    let var_y = points
+        .iter()
+        .map(|(_, y, w)| w * (y - cy).powi(2))
+        .sum::<f64>()
+        / total;
+    let geo = points
+        .iter()
+        .filter(|(_, _, w)| *w > 0.0)
+        .map(|(x, y, _)| (x - cx).hypot(y - cy))
+        .fold(0.0, f64::max);
+    Some(SpotStats {
+        centroid_x: cx,
+        centroid_y: cy,
+        rms_radius: (var_x + var_y).sqrt(),
+        geo_radius: geo,
+        rms_x: var_x.sqrt(),
+        rms_y: var_y.sqrt(),
+        num_rays: points.len(),
+    })
+}
+
+// Radius of the first dark ring, the wavelength in um and the lens in mm. The image space NA
+// is the angle between the chief ray and the ray through the +y edge of a pupil of
+// pupil_radius, so a beam that underfills the aperture gets its own larger ring. When that ray
+// fails the aperture limits the beam and the paraxial working f-number is used.
+pub fn airy_radius(
+    lens: &Lens,
+    chief: &Ray,
+    pupil_radius: f64,
+    refocus: f64,
+    wavelength: f64,
+) -> f64 {
+    let edge = trace_ray(&pupil_ray(chief, lens, 0.0, pupil_radius), lens, refocus);
+    let image = trace_ray(chief, lens, refocus);
+    let na = match (edge, image) {
+        (Ok(edge), Ok(image)) => {
+            let cos = edge.edir.dot_product(&image.edir) / edge.edir.length() / image.edir.length();
+            let n_image = lens.surfaces.last().map_or(1.0, |s| s.n_index);
+            n_image * (1.0 - cos * cos).max(0.0).sqrt()
+        }
+        _ => 1.0 / (2.0 * first_order(lens).working_f_number),
+    };
+    0.61e-3 * wavelength / na
+}
+
+// Spot of the field at each wavelength from pupil points relative to the chief ray on z = 0,
+// taken from a pupil of pupil_radius. lenses and refocus come from lenses_at and common_refocus,
+// weights are normalized. The chief ray of the first wavelength aims every wavelength.
+pub fn field_spot(
+    lenses: &[Lens],
+    refocus: &[f64],
+    wavelengths: &[f64],
+    weights: &[f64],
+    field: &Field,
+    pupil: &[(f64, f64)],
+    pupil_radius: f64,
+) -> Result<FieldSpot, FieldError> {
+    let chief = chief_ray(field, &lenses[0])?;
+    // the ring of the first wavelength, scaled to the others
+    let airy = airy_radius(&lenses[0], &chief, pupil_radius, refocus[0], 1.0);
+
+    let mut spots = Vec::with_capacity(lenses.len());
+    let mut weighted = vec![];
+    for (((lens, refocus), wavelength), weight) in
+        lenses.iter().zip(refocus).zip(wavelengths).zip(weights)
+    {
+        let points = pupil
+            .iter()
+            .filter_map(|(x, y)| trace_ray(&pupil_ray(&chief, lens, *x, *y), lens, *refocus).ok())
+            .map(|r| (r.pvector.x, r.pvector.y))
+            .collect::<Vec<(f64, f64)>>();
+        let ray_weight = weight / points.len().max(1) as f64;
+        weighted.extend(points.iter().map(|(x, y)| (*x, *y, ray_weight)));
+        spots.push(WavelengthSpot {
+            wavelength: *wavelength,
+            airy_radius: airy * wavelength,
+            stats: spot_stats(
+                &points
+                    .iter()
+                    .map(|(x, y)| (*x, *y, 1.0))
+                    .collect::<Vec<_>>(),
+            ),
+            num_failed: pupil.len() - points.len(),
+            points,
+        });
+    }
+    Ok(FieldSpot {
+        field: *field,
+        wavelengths: spots,
+        polychromatic: spot_stats(&weighted),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lens::tests::bk7_singlet;
+    use crate::raytrace::chromatic::{common_refocus, lenses_at};
+
+    #[test]
+    fn stats_of_known_points() {
+        let points = [
+            (1.0, 2.0, 1.0),
+            (3.0, 2.0, 1.0),
+            (2.0, 1.0, 1.0),
+            (2.0, 3.0, 1.0),
+        ];
+        let stats = spot_stats(&points).unwrap();
+        assert_eq!((stats.centroid_x, stats.centroid_y), (2.0, 2.0));
+        assert_eq!(stats.geo_radius, 1.0);
+        assert_eq!(stats.rms_radius, 1.0);
+        assert_eq!(stats.rms_x, 0.5f64.sqrt());
+        assert_eq!(stats.num_rays, 4);
+
+        // a weight of zero drops the point from everything but the count
+        let stats = spot_stats(&[(0.0, 0.0, 1.0), (4.0, 0.0, 0.0)]).unwrap();
+        assert_eq!(stats.centroid_x, 0.0);
+        assert_eq!(stats.rms_radius, 0.0);
+        assert_eq!(stats.geo_radius, 0.0);
+        assert_eq!(spot_stats(&[]), None);
+    }
+
+    #[test]
+    fn singlet_spot_grows_off_axis() {
+        let lens = bk7_singlet();
+        let wavelengths = [0.5876, 0.4861];
+        let lenses = lenses_at(&lens, &wavelengths).unwrap();
+        let refocus = common_refocus(&lenses, 0.0);
+        let pupil = [(0.0, 0.0), (0.0, 5.0), (0.0, -5.0), (5.0, 0.0), (-5.0, 0.0)];
+
+        let spot = |field| {
+            field_spot(
+                &lenses,
+                &refocus,
+                &wavelengths,
+                &[0.5, 0.5],
+                &field,
+                &pupil,
+                5.0,
+            )
+            .unwrap()
+        };
+        let on_axis = spot(Field::default());
+        let off_axis = spot(Field::ObjectAngle { x: 0.0, y: 0.05 });
+
+        let d = on_axis.wavelengths[0].stats.unwrap();
+        assert!(d.centroid_x.abs() < 1e-12 && d.centroid_y.abs() < 1e-12);
+        assert!((d.rms_x - d.rms_y).abs() < 1e-12);
+        assert_eq!(on_axis.wavelengths[0].num_failed, 0);
+
+        // blue is out of focus on the image plane of d, so its spot is larger
+        let f = on_axis.wavelengths[1].stats.unwrap();
+        assert!(f.rms_radius > d.rms_radius);
+        let poly = on_axis.polychromatic.unwrap();
+        assert!(poly.rms_radius > d.rms_radius && poly.num_rays == 10);
+
+        let tilted = off_axis.wavelengths[0].stats.unwrap();
+        assert!(tilted.centroid_y.abs() > 1.0);
+        assert!(tilted.rms_radius > d.rms_radius);
+
+        // the Airy radius scales with the wavelength at the f-number of the first
+        let ratio = on_axis.wavelengths[1].airy_radius / on_axis.wavelengths[0].airy_radius;
+        assert!((ratio - 0.4861 / 0.5876).abs() < 1e-12);
+
+        // a 5 mm beam through the 24 mm aperture is about F/5 rather than the lens's F/2
+        let chief = chief_ray(&Field::default(), &lenses[0]).unwrap();
+        let beam = airy_radius(&lenses[0], &chief, 5.0, refocus[0], 0.5876);
+        let full = airy_radius(&lenses[0], &chief, 12.0, refocus[0], 0.5876);
+        assert!(full < beam / 2.0);
+        let efl = first_order(&lenses[0]).efl;
+        assert!((beam - 1.22e-3 * 0.5876 * efl / 10.0).abs() < 0.02 * beam);
+    }
+}
@@ -1,6 +1,6 @@
 use super::{
     ray_vector::{Ray, Vector3D, CPROPV},
-    trace_ray,
+    trace_ray, trace_ray_opl, translate_to_flat, RayFailure, TraceError,
 };
 use crate::lens::Lens;
 use std::f64::consts::SQRT_2;
@@ -23,6 +23,7 @@ pub struct WFE_Stats {
     pub minopd: f64,
     pub maxopd: f64,
     pub varirms: f64,
+    pub num_failed: usize,
 }
 
 #[repr(C)]
@@ -32,6 +33,7 @@ pub struct ErrorStat {
     pv: f64,
     average: f64,
     rms: f64,
+    num_failed: usize,
 }
 
 fn calc_wfe_stats(
@@ -47,11 +49,12 @@ fn calc_wfe_stats(
     let mut sumsum: f64 = 0.0;
     let mut peak: f64 = -1.0e20;
     let mut valley: f64 = 1.0e20;
+    let mut num_failed = 0;
 
     let mut _mlens: Lens = lens.clone();
 
     for i in 0..rayct {
-        let wfe = calc_opd_slim(
+        let Ok(wfe) = calc_opd_slim(
             Vector3D {
                 x: 0.0,
                 y: ray_ys[i] * lens.diameter / 2.,
@@ -61,7 +64,10 @@ fn calc_wfe_stats(
             &lens,
             wavelength,
             refocus,
-        );
+        ) else {
+            num_failed += 1;
+            continue;
+        };
         if wfe > peak {
             peak = wfe
         }
@@ -71,12 +77,14 @@ fn calc_wfe_stats(
         sum += wfe;
         sumsum += wfe * wfe;
     }
+    let traced = (rayct - num_failed) as f64;
     let estat = ErrorStat {
         peak,
         valley,
         pv: (peak - valley),
-        average: sum / rayct as f64,
-        rms: (sumsum / rayct as f64).sqrt(),
+        average: sum / traced,
+        rms: (sumsum / traced).sqrt(),
+        num_failed,
     };
     return estat;
 }
@@ -88,7 +96,7 @@ pub fn calc_opd_slim(
     lens: &Lens,
     wavelength: f64,
     refocus: f64,
-) -> f64 {
+) -> Result<f64, TraceError> {
     //let sqr2 = 2_f64.sqrt();
 
     let p1 = Vector3D {
@@ -100,7 +108,7 @@ pub fn calc_opd_slim(
     let rsq = p0.x * p0.x + p0.y * p0.y;
 
     if rsq < 1.0e-10 {
-        return 0.0_f64;
+        return Ok(0.0_f64);
     }
 
     let rsqsq = rsq * rsq;
@@ -112,7 +120,7 @@ pub fn calc_opd_slim(
         },
         lens,
         0.0,
-    );
+    )?;
     //let ym = rm.pvector;
     let (ymaoi, ymlsa) = rm.calc_aoi_lsa();
 
@@ -123,7 +131,7 @@ pub fn calc_opd_slim(
         },
         lens,
         0.0,
-    );
+    )?;
     //let yz = rz.pvector;
     let (_yzaoi, yzlsa) = rz.calc_aoi_lsa();
 
@@ -134,18 +142,116 @@ pub fn calc_opd_slim(
         },
         lens,
         refocus,
-    );
+    )?;
     //let yfinal = rfinal.pvector;
     let (_yfaoi, _yflsa) = rfinal.calc_aoi_lsa();
 
     let a = (4.0 * yzlsa - ymlsa) / rsq;
     let b = (2.0 * ymlsa - 4.0 * yzlsa) / rsqsq;
 
-    1000.0 * (ymaoi.sin() * ymaoi.sin() / 2.0) * (refocus - a * rsq / 2.0 - b * rsqsq / 3.0)
-        / wavelength
+    Ok(
+        1000.0 * (ymaoi.sin() * ymaoi.sin() / 2.0) * (refocus - a * rsq / 2.0 - b * rsqsq / 3.0)
+            / wavelength,
+    )
+}
+
+// Reference sphere for optical path OPD, centered on the chief ray image point and passing
+// through the chief ray at the paraxial exit pupil. A telecentric image space uses the plane
+// normal to the chief ray instead.
+pub struct OpdReference {
+    center: Vector3D,
+    chief_dir: Vector3D,
+    radius: Option<f64>,
+    upstream: bool,
+    n_image: f64,
+    chief_opl: f64,
+}
+
+impl OpdReference {
+    pub fn new(chief: &Ray, lens: &Lens, refocus: f64) -> Result<OpdReference, TraceError> {
+        let (rc, opl) = trace_ray_opl(chief, lens, refocus)?;
+        let n_image = lens.surfaces.last().map_or(1.0, |s| s.n_index);
+
+        let zpupil = lens.exit_pupil();
+        let (radius, upstream) = if zpupil.is_finite() {
+            let to_pupil = translate_to_flat(&rc.pvector, &rc.edir, zpupil) - &rc.pvector;
+            (
+                Some(to_pupil.length()),
+                to_pupil.dot_product(&rc.edir) < 0.0,
+            )
+        } else {
+            (None, true)
+        };
+
+        let mut reference = OpdReference {
+            center: rc.pvector,
+            chief_dir: rc.edir,
+            radius,
+            upstream,
+            n_image,
+            chief_opl: 0.0,
+        };
+        reference.chief_opl = opl
+            + n_image
+                * reference
+                    .distance(&reference.center, &reference.chief_dir)
+                    .unwrap_or(0.0);
+        Ok(reference)
+    }
+
+    // signed distance along e from p back to the reference surface
+    fn distance(&self, p: &Vector3D, e: &Vector3D) -> Option<f64> {
+        let d = p - &self.center;
+        match self.radius {
+            Some(radius) => {
+                let b = d.dot_product(e);
+                let disc = b * b - d.dot_product(&d) + radius * radius;
+                if disc < 0.0 {
+                    None
+                } else if self.upstream {
+                    Some(-b - disc.sqrt())
+                } else {
+                    Some(-b + disc.sqrt())
+                }
+            }
+            None => Some(-d.dot_product(&self.chief_dir) / e.dot_product(&self.chief_dir)),
+        }
+    }
 }
 
-fn rcalc_wfe(p0: Vector3D, e0: Vector3D, lens: &Lens, wavelength: f64, refocus: f64) -> f64 {
+// OPD in waves from the optical path length of the real ray, positive when the ray's path to
+// the reference sphere is shorter than the chief ray's
+pub fn calc_opd_opl(
+    p0: Vector3D,
+    e0: Vector3D,
+    lens: &Lens,
+    wavelength: f64,
+    refocus: f64,
+    reference: &OpdReference,
+) -> Result<f64, TraceError> {
+    let (r, opl) = trace_ray_opl(
+        &Ray {
+            pvector: p0,
+            edir: e0,
+        },
+        lens,
+        refocus,
+    )?;
+    let s = reference.distance(&r.pvector, &r.edir).ok_or(TraceError {
+        surface: lens.surfaces.len(),
+        failure: RayFailure::Missed,
+    })?;
+
+    Ok(1000.0 * (reference.chief_opl - opl - reference.n_image * s) / wavelength)
+}
+
+fn rcalc_wfe(
+    p0: Vector3D,
+    e0: Vector3D,
+    lens: &Lens,
+    wavelength: f64,
+    refocus: f64,
+) -> Result<f64, TraceError> {
     let p1 = Vector3D {
         x: (p0.x / SQRT_2),
         y: (p0.y / SQRT_2),
@@ -162,7 +268,7 @@ fn rcalc_wfe(p0: Vector3D, e0: Vector3D, lens: &Lens, wavelength: f64, refocus:
         },
         lens,
         0.0,
-    );
+    )?;
     //let ym = rm.pvector;
     let (ymaoi, ymlsa) = rm.calc_aoi_lsa();
 
@@ -173,7 +279,7 @@ fn rcalc_wfe(p0: Vector3D, e0: Vector3D, lens: &Lens, wavelength: f64, refocus:
         },
         lens,
         0.0,
-    );
+    )?;
     //let yz = rz.pvector;
     let (_yzaoi, yzlsa) = rz.calc_aoi_lsa();
 
@@ -184,8 +290,10 @@ fn rcalc_wfe(p0: Vector3D, e0: Vector3D, lens: &Lens, wavelength: f64, refocus:
     let a = (4.0 * yzlsa - ymlsa) / rsq;
     let b = (2.0 * ymlsa - 4.0 * yzlsa) / rsqsq;
 
-    1000.0 * (ymaoi.sin() * ymaoi.sin() / 2.0) * (refocus - a * rsq / 2.0 - b * rsqsq / 3.0)
-        / wavelength
+    Ok(
+        1000.0 * (ymaoi.sin() * ymaoi.sin() / 2.0) * (refocus - a * rsq / 2.0 - b * rsqsq / 3.0)
+            / wavelength,
+    )
 }
 
 fn gen_and_trace_wfe_rays(
@@ -204,6 +312,7 @@ fn gen_and_trace_wfe_rays(
         minopd: 1e20,
         maxopd: -1e20,
         varirms: 0.0,
+        num_failed: 0,
     };
 
     let mut xsum = 0.0;
@@ -212,19 +321,21 @@ fn gen_and_trace_wfe_rays(
 
     for i in 0..npts {
         if din[i].isvalid {
-            calc_wfe_ray(&mut din[i], &lens, wavelength, refocus);
-            if !din[i].opd.is_nan() {
-                if din[i].opd > wstats.maxopd {
-                    wstats.maxopd = din[i].opd;
-                }
-                if din[i].opd < wstats.minopd {
-                    wstats.minopd = din[i].opd;
-                }
-
-                xsum += din[i].opd;
-                xsumsq += din[i].opd * din[i].opd;
-                cts += 1.0;
+            if calc_wfe_ray(&mut din[i], &lens, wavelength, refocus).is_err() {
+                din[i].isvalid = false;
+                wstats.num_failed += 1;
+                continue;
+            }
+            if din[i].opd > wstats.maxopd {
+                wstats.maxopd = din[i].opd;
+            }
+            if din[i].opd < wstats.minopd {
+                wstats.minopd = din[i].opd;
             }
+
+            xsum += din[i].opd;
+            xsumsq += din[i].opd * din[i].opd;
+            cts += 1.0;
         }
     }
     wstats.varirms = ((xsumsq - xsum * xsum / cts) / (cts - 1.0)).sqrt();
@@ -258,7 +369,12 @@ fn gen_wfe_rays(apert: f64, _size: usize, din: &mut [WFE_Ray], loopsize: usize)
     }
 }
 
-fn calc_wfe_ray(wferay: &mut WFE_Ray, lens: &Lens, wavelength: f64, refocus: f64) {
+fn calc_wfe_ray(
+    wferay: &mut WFE_Ray,
+    lens: &Lens,
+    wavelength: f64,
+    refocus: f64,
+) -> Result<(), TraceError> {
     let p0 = wferay.rstart.pvector.clone();
     let e0 = wferay.rstart.edir.clone();
 
@@ -276,12 +392,13 @@ fn calc_wfe_ray(wferay: &mut WFE_Ray, lens: &Lens, wavelength: f64, refocus: f64
             pvector: Vector3D {
                 x: 0.0,
                 y: 0.0,
-                z: lens.ct + lens.bfl() + refocus,
+                z: lens.image_plane(refocus),
             },
             edir: CPROPV,
         };
         wferay.lsa = 0.0;
         wferay.opd = 0.0;
+        return Ok(());
     }
 
     let rsqsq = rsq * rsq;
@@ -293,7 +410,7 @@ fn calc_wfe_ray(wferay: &mut WFE_Ray, lens: &Lens, wavelength: f64, refocus: f64
         },
         lens,
         0.0,
-    );
+    )?;
     //let ym = rm.pvector;
     let (ymaoi, ymlsa) = rm.calc_aoi_lsa();
 
@@ -304,7 +421,7 @@ fn calc_wfe_ray(wferay: &mut WFE_Ray, lens: &Lens, wavelength: f64, refocus: f64
         },
         lens,
         0.0,
-    );
+    )?;
     //let yz = rz.pvector;
     let (_yzaoi, yzlsa) = rz.calc_aoi_lsa();
 
@@ -315,7 +432,7 @@ fn calc_wfe_ray(wferay: &mut WFE_Ray, lens: &Lens, wavelength: f64, refocus: f64
         },
         lens,
         refocus,
-    );
+    )?;
     //let yfinal = rfinal.pvector;
     let (_yfaoi, yflsa) = rfinal.calc_aoi_lsa();
     wferay.rend = rfinal;
@@ -327,4 +444,58 @@ fn calc_wfe_ray(wferay: &mut WFE_Ray, lens: &Lens, wavelength: f64, refocus: f64
     wferay.opd =
         1000.0 * (ymaoi.sin() * ymaoi.sin() / 2.0) * (refocus - a * rsq / 2.0 - b * rsqsq / 3.0)
             / wavelength;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lens::{Side, Surface};
+
+    fn chief() -> Ray {
+        Ray {
+            pvector: Vector3D {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            edir: CPROPV,
+        }
+    }
+
+    #[test]
+    fn opl_matches_lsa_for_spherical() {
+        // plano convex singlet, low order spherical where the LSA fit is valid
+        let lens = Lens::singlet(
+            25.0,
+            24.0,
+            5.0,
+            1.5,
+            Side::new(40.0, 0.0, vec![]),
+            Side::new(0.0, 0.0, vec![]),
+        );
+        for refocus in [0.0, -0.3] {
+            let reference = OpdReference::new(&chief(), &lens, refocus).unwrap();
+            for y in [2.0, 5.0, 8.0] {
+                let p = Vector3D { x: 0.0, y, z: 0.0 };
+                let lsa = calc_opd_slim(p.clone(), CPROPV, &lens, 0.6328, refocus).unwrap();
+                let opl = calc_opd_opl(p, CPROPV, &lens, 0.6328, refocus, &reference).unwrap();
+                assert!((lsa - opl).abs() < 0.02 * lsa.abs().max(0.5));
+            }
+        }
+    }
+
+    #[test]
+    fn parabola_has_no_opd() {
+        let mut parabola = Surface::new(Side::new(-200.0, -1.0, vec![]), 0.0, 1.0);
+        parabola.mirror = true;
+        let lens = Lens::new(50.0, 48.0, vec![parabola]);
+        let reference = OpdReference::new(&chief(), &lens, 0.0).unwrap();
+
+        for (x, y) in [(0.0, 5.0), (12.0, -9.0), (0.0, 20.0)] {
+            let p = Vector3D { x, y, z: 0.0 };
+            let opd = calc_opd_opl(p, CPROPV, &lens, 0.6328, 0.0, &reference).unwrap();
+            assert!(opd.abs() < 1e-6);
+        }
+    }
 }
@@ -0,0 +1,344 @@
+// Third order (Seidel) aberration sums from the paraxial marginal and chief rays, following
+// Welford's refraction invariant form. The sums are wavefront coefficients in lens units:
+// W040 = S1 / 8, W131 = S2 / 2, W222 = S3 / 2, W220 = (S3 + S4) / 4, W311 = S5 / 2,
+// W020 = C1 / 2 and W111 = C2 over the F - C dispersion. Conics and aspheres add Welford's
+// aspheric term from the fourth order departure of the surface from its base sphere.
+
+use crate::lens::{AsphereDefinition, Lens, Side};
+use crate::paraxial::{self, signed_index, ParaxialRay};
+use crate::qpoly::{solve_linear, to_standard};
+use crate::raytrace::{
+    field::{ray_from_object, Field},
+    ray_vector::{Ray, Vector3D, CPROPV},
+    trace_ray,
+};
+use serde::Serialize;
+use std::ops::AddAssign;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct SeidelSums {
+    pub spherical: f64,
+    pub coma: f64,
+    pub astigmatism: f64,
+    pub field_curvature: f64,
+    pub distortion: f64,
+    pub axial_color: f64,
+    pub lateral_color: f64,
+}
+
+impl AddAssign for SeidelSums {
+    fn add_assign(&mut self, other: SeidelSums) {
+        self.spherical += other.spherical;
+        self.coma += other.coma;
+        self.astigmatism += other.astigmatism;
+        self.field_curvature += other.field_curvature;
+        self.distortion += other.distortion;
+        self.axial_color += other.axial_color;
+        self.lateral_color += other.lateral_color;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeidelError {
+    Anamorphic(usize), // biconic, toroid or freeform surface, no rotational third order sums
+    LowOrderTerms(usize), // odd asphere with r, r^2 or r^3 terms
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Seidel {
+    pub surfaces: Vec<SeidelSums>,
+    pub total: SeidelSums,
+    pub lagrange_invariant: f64,
+    // W060 of the on axis wavefront fit to real rays, NaN when too few rays get through
+    pub spherical5: f64,
+}
+
+// Seidel sums for the lens at a field. Only the size of the field is used. dispersion holds
+// n_F - n_C of the medium after each surface, missing entries are taken as zero.
+pub fn seidel(lens: &Lens, field: &Field, dispersion: &[f64]) -> Result<Seidel, SeidelError> {
+    let mut marginal = paraxial::marginal_ray(lens);
+    let mut chief = paraxial::chief_ray(lens, paraxial_field(lens, field));
+    let lagrange_invariant = chief.nu * marginal.y - marginal.nu * chief.y;
+
+    let mut n = 1.;
+    let mut dn = 0.;
+    let mut surfaces = Vec::with_capacity(lens.surfaces.len());
+    let mut total = SeidelSums::default();
+
+    for (i, surf) in lens.surfaces.iter().enumerate() {
+        let c = surf.side.curv();
+        let b = fourth_order(&surf.side, i)?;
+        let n_next = signed_index(surf, n);
+        // the dispersion follows the sign of the index through mirrors
+        let dn_next = n_next.signum() * dispersion.get(i).copied().unwrap_or(0.);
+
+        // refraction invariants
+        let a = n * marginal.y * c + marginal.nu;
+        let a_bar = n * chief.y * c + chief.nu;
+
+        let nu_next = marginal.nu - marginal.y * c * (n_next - n);
+        let d_u_n = nu_next / (n_next * n_next) - marginal.nu / (n * n);
+        let d_inv_n = 1. / n_next - 1. / n;
+        let d_inv_n2 = 1. / (n_next * n_next) - 1. / (n * n);
+        let d_dn_n = dn_next / n_next - dn / n;
+
+        let y = marginal.y;
+        // aspheric S1 = 8 b (n' - n) y^4, carried into S2, S3 and S5 with powers of y_bar / y
+        let asph = 8. * b * (n_next - n);
+        let y_bar = chief.y;
+        let sums = SeidelSums {
+            spherical: -a * a * y * d_u_n + asph * y.powi(4),
+            coma: -a * a_bar * y * d_u_n + asph * y.powi(3) * y_bar,
+            astigmatism: -a_bar * a_bar * y * d_u_n + asph * y * y * y_bar * y_bar,
+            field_curvature: -lagrange_invariant.powi(2) * c * d_inv_n,
+            // (a_bar / a) (S3 + S4) without the division, which fails for a flat surface in a
+            // collimated beam
+            distortion: -a_bar.powi(3) * y * d_inv_n2
+                + a_bar * chief.y * c * d_inv_n * (a_bar * y + lagrange_invariant)
+                + asph * y * y_bar.powi(3),
+            axial_color: a * y * d_dn_n,
+            lateral_color: a_bar * y * d_dn_n,
+        };
+        total += sums;
+        surfaces.push(sums);
+
+        let chief_nu = chief.nu - chief.y * c * (n_next - n);
+        marginal = ParaxialRay::new(marginal.y + surf.thickness * nu_next / n_next, nu_next);
+        chief = ParaxialRay::new(chief.y + surf.thickness * chief_nu / n_next, chief_nu);
+        n = n_next;
+        dn = dn_next;
+    }
+
+    Ok(Seidel {
+        surfaces,
+        total,
+        lagrange_invariant,
+        spherical5: fifth_order_spherical(lens),
+    })
+}
+
+// r^4 coefficient of the sag departure from the base sphere, k c^3 / 8 from the conic plus the
+// r^4 term of the polynomial. Qbfs takes the r^4 term of its standard fit.
+fn fourth_order(side: &Side, surface: usize) -> Result<f64, SeidelError> {
+    let a4 = match side.definition {
+        AsphereDefinition::Standard
+        | AsphereDefinition::Qcon { .. }
+        | AsphereDefinition::Qbfs { .. } => to_standard(side).0.coeffs.first().copied(),
+        AsphereDefinition::Odd => {
+            if side.coeffs.iter().take(3).any(|a| *a != 0.) {
+                return Err(SeidelError::LowOrderTerms(surface));
+            }
+            side.coeffs.get(3).copied()
+        }
+        AsphereDefinition::Biconic { .. }
+        | AsphereDefinition::Toroid { .. }
+        | AsphereDefinition::XyPolynomial { .. }
+        | AsphereDefinition::Zernike { .. } => return Err(SeidelError::Anamorphic(surface)),
+    };
+    Ok(side.k * side.curv().powi(3) / 8. + a4.unwrap_or(0.))
+}
+
+// size of the field in the units of paraxial::chief_ray
+fn paraxial_field(lens: &Lens, field: &Field) -> f64 {
+    match (*field, lens.object_distance) {
+        (Field::ObjectAngle { x, y }, None) => x.tan().hypot(y.tan()),
+        (Field::ObjectAngle { x, y }, Some(dist)) => dist * x.tan().hypot(y.tan()),
+        (Field::ObjectHeight { x, y }, _) => x.hypot(y),
+        (Field::ImageHeight { x, y }, _) => {
+            // the image height scales with the field
+            let (unit, n) = paraxial::trace(&lens.surfaces, paraxial::chief_ray(lens, 1.), 1.);
+            let image = unit.height_at(lens.image_distance(), n);
+            (x.hypot(y) / image).abs()
+        }
+    }
+}
+
+// Fit the transverse aberration of real axial rays at the paraxial image to odd powers of the
+// pupil coordinate and convert the fifth order term to W060.
+fn fifth_order_spherical(lens: &Lens) -> f64 {
+    let marginal = paraxial::marginal_ray(lens);
+    let (image_marginal, _) = paraxial::trace(&lens.surfaces, marginal, 1.);
+    let powers = [3, 5, 7];
+
+    let mut ata = vec![vec![0.0; powers.len()]; powers.len()];
+    let mut atb = vec![0.0; powers.len()];
+    let mut num_rays = 0;
+    for k in 1..=16 {
+        let rho = k as f64 / 16.;
+        let height = rho * marginal.y;
+        let ray = match lens.object_plane() {
+            None => Ray {
+                pvector: Vector3D {
+                    x: 0.,
+                    y: height,
+                    z: 0.,
+                },
+                edir: CPROPV,
+            },
+            Some(z) => ray_from_object(&Vector3D { x: 0., y: 0., z }, 0., height),
+        };
+        let Ok(image) = trace_ray(&ray, lens, 0.) else {
+            continue;
+        };
+
+        let row = powers.map(|p| rho.powi(p));
+        for i in 0..powers.len() {
+            atb[i] += row[i] * image.pvector.y;
+            for j in 0..powers.len() {
+                ata[i][j] += row[i] * row[j];
+            }
+        }
+        num_rays += 1;
+    }
+    if num_rays < powers.len() {
+        return f64::NAN;
+    }
+
+    // TA = dW/drho / nu'
+    let coeffs = solve_linear(ata, atb);
+    image_marginal.nu * coeffs[1] / 6.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lens::{Side, Surface};
+
+    fn plano(thickness: f64) -> Surface {
+        Surface::new(Side::new(0., 0., vec![]), thickness, 1.)
+    }
+
+    #[test]
+    fn mirror_stopped_at_center_of_curvature() {
+        // only spherical and field curvature survive
+        let mut stop = plano(100.);
+        stop.clear_ap = Some(20.);
+        let mut mirror = Surface::new(Side::new(-100., 0., vec![]), -50., 1.);
+        mirror.mirror = true;
+        let mut lens = Lens::new(40., 0., vec![stop, mirror]);
+        lens.stop = Some(0);
+
+        let field = Field::ObjectAngle { x: 0., y: 0.05 };
+        let sums = seidel(&lens, &field, &[]).unwrap().total;
+        let (y, c): (f64, f64) = (10., -0.01);
+        let h = 10. * 0.05_f64.tan();
+
+        assert!((sums.spherical + 2. * y.powi(4) * c.powi(3)).abs() < 1e-12);
+        assert!(sums.coma.abs() < 1e-12);
+        assert!(sums.astigmatism.abs() < 1e-12);
+        assert!(sums.distortion.abs() < 1e-12);
+        assert!((sums.field_curvature - 2. * h * h * c).abs() < 1e-12);
+        assert_eq!(sums.axial_color, 0.);
+    }
+
+    #[test]
+    fn thin_lens_petzval_and_color() {
+        let (f, n, v) = (50., 1.5, 60.);
+        let lens = Lens::singlet(
+            20.,
+            20.,
+            0.,
+            n,
+            Side::new(f, 0., vec![]),
+            Side::new(-f, 0., vec![]),
+        );
+        let field = Field::ObjectAngle { x: 0.1, y: 0. };
+        let result = seidel(&lens, &field, &[(n - 1.) / v, 0.]).unwrap();
+        let h = result.lagrange_invariant;
+
+        // thin lens at the stop: S4 = H^2 phi / n, C1 = y^2 phi / V, no lateral color
+        assert!((h - 10. * 0.1_f64.tan()).abs() < 1e-12);
+        assert!((result.total.field_curvature - h * h / (f * n)).abs() < 1e-12);
+        assert!((result.total.axial_color - 100. / (f * v)).abs() < 1e-12);
+        assert!(result.total.lateral_color.abs() < 1e-12);
+        assert_eq!(result.surfaces.len(), 2);
+    }
+
+    #[test]
+    fn spherical_matches_real_rays() {
+        // plano-convex at f/10, the real ray fit recovers S1 / 8 and a small fifth order term
+        let lens = Lens::singlet(
+            10.,
+            10.,
+            3.,
+            1.5,
+            Side::new(50., 0., vec![]),
+            Side::new(0., 0., vec![]),
+        );
+        let result = seidel(&lens, &Field::default(), &[]).unwrap();
+        assert!(result.total.spherical > 0.);
+        assert!(result.spherical5 > 0.);
+        assert!(result.spherical5 < 0.05 * result.total.spherical / 8.);
+
+        // the third order term of the same fit
+        let w040 = result.total.spherical / 8.;
+        let marginal = paraxial::marginal_ray(&lens);
+        let (image, _) = paraxial::trace(&lens.surfaces, marginal, 1.);
+        let ray = Ray {
+            pvector: Vector3D {
+                x: 0.,
+                y: marginal.y,
+                z: 0.,
+            },
+            edir: CPROPV,
+        };
+        let ta = trace_ray(&ray, &lens, 0.).unwrap().pvector.y;
+        let w_real = image.nu * ta / 4.;
+        assert!((w_real - w040).abs() < 0.05 * w040);
+    }
+
+    #[test]
+    fn parabola_has_no_spherical() {
+        // the sphere's S1 is cancelled by the conic, coma and the rest are unchanged
+        let mut mirror = Surface::new(Side::new(-100., -1., vec![]), -50., 1.);
+        mirror.mirror = true;
+        let lens = Lens::new(40., 40., vec![mirror.clone()]);
+        let field = Field::ObjectAngle { x: 0., y: 0.05 };
+        let parabola = seidel(&lens, &field, &[]).unwrap().total;
+        assert!(parabola.spherical.abs() < 1e-12);
+
+        mirror.side.k = 0.;
+        let sphere = seidel(&Lens::new(40., 40., vec![mirror]), &field, &[])
+            .unwrap()
+            .total;
+        assert!(sphere.spherical.abs() > 1e-6);
+        assert!((parabola.coma - sphere.coma).abs() < 1e-12);
+    }
+
+    #[test]
+    fn asphere_matches_real_rays() {
+        // a strong r^4 term on the plano-convex of spherical_matches_real_rays
+        let lens = Lens::singlet(
+            10.,
+            10.,
+            3.,
+            1.5,
+            Side::new(50., -2., vec![-2e-5]),
+            Side::new(0., 0., vec![]),
+        );
+        let w040 = seidel(&lens, &Field::default(), &[])
+            .unwrap()
+            .total
+            .spherical
+            / 8.;
+        let marginal = paraxial::marginal_ray(&lens);
+        let (image, _) = paraxial::trace(&lens.surfaces, marginal, 1.);
+        let ray = Ray {
+            pvector: Vector3D {
+                x: 0.,
+                y: marginal.y,
+                z: 0.,
+            },
+            edir: CPROPV,
+        };
+        let ta = trace_ray(&ray, &lens, 0.).unwrap().pvector.y;
+        let w_real = image.nu * ta / 4.;
+        assert!(w040 < 0.);
+        assert!((w_real - w040).abs() < 0.05 * w040.abs());
+
+        let mut toric = lens.clone();
+        toric.surfaces[1].side.definition = AsphereDefinition::Toroid { ry: 40., ky: 0. };
+        let err = seidel(&toric, &Field::default(), &[]).unwrap_err();
+        assert_eq!(err, SeidelError::Anamorphic(1));
+    }
+}
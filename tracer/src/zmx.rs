@@ -0,0 +1,714 @@
+// Reader and writer for Zemax ZMX sequential prescriptions. SURF 0 is the object and the last
+// SURF the image, the surfaces between them become the lens. Each surface collects the indented
+// records that follow it:
+// TYPE kind   CURV c   CONI k   PARM i value   DISZ thickness   DIAM semi diameter
+// GLAS name ... nd vd ...   STOP
+// The system records read are NAME, UNIT, ENPD, WAVM and PWAV, everything else is skipped.
+// Lengths are converted to mm.
+//
+// A tracer CoordBreak only moves its own surface while a Zemax coordinate break moves every
+// surface after it, so a COORDBRK is only exact when a second one right after the next surface
+// undoes it. This is how the writer emits a decentered or tilted surface.
+
+use crate::lens::{AsphereDefinition, CoordBreak, Lens, Side, Surface};
+use crate::material::Material;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZmxError {
+    // an object, at least one lens surface and an image are needed
+    TooFewSurfaces,
+}
+
+// Anything the prescription uses that the tracer cannot represent. The surface numbers are the
+// Zemax SURF numbers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ZmxWarning {
+    BadNumber { line: usize },
+    UnknownUnit(String),
+    NoWavelengths,
+    // traced with only its curvature and conic
+    UnsupportedSurface { surface: usize, kind: String },
+    // a PARM the surface type has no term for, dropped
+    UnsupportedParameter { surface: usize, parm: usize },
+    // not in the built in or registered materials, its model nd is used at every wavelength
+    UnknownGlass { surface: usize, glass: String },
+    // a coordinate break that is not undone after the next surface or tilts before decentering
+    UnsupportedCoordBreak { surface: usize },
+}
+
+// A lens with the system data of a ZMX file. Wavelengths are in microns and primary_wavelength
+// indexes them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZmxSystem {
+    pub name: String,
+    pub lens: Lens,
+    pub wavelengths: Vec<f64>,
+    pub weights: Vec<f64>,
+    pub primary_wavelength: usize,
+    pub entrance_pupil_diameter: Option<f64>,
+}
+
+// surface being parsed, turned into a tracer surface once the whole file is read
+struct Entry {
+    number: usize,
+    kind: String,
+    curv: f64,
+    conic: f64,
+    parms: Vec<(usize, f64)>,
+    thickness: f64,
+    semi_diameter: f64,
+    glass: Vec<String>,
+    stop: bool,
+}
+
+pub fn parse_zmx(text: &str) -> Result<(ZmxSystem, Vec<ZmxWarning>), ZmxError> {
+    let mut warnings = vec![];
+    let mut name = String::new();
+    let mut scale = 1.0;
+    let mut entrance_pupil_diameter = None;
+    let mut wavelengths = vec![];
+    let mut weights = vec![];
+    let mut primary_wavelength = 0;
+    let mut entries: Vec<Entry> = vec![];
+
+    for (i, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line_number = i + 1;
+        let indented = line.starts_with(char::is_whitespace);
+        let mut fields = line.split_whitespace();
+        let Some(record) = fields.next() else {
+            continue;
+        };
+        let fields = fields.collect::<Vec<&str>>();
+        let number = |i: usize| fields.get(i).and_then(|f| parse_number(f));
+
+        if indented {
+            let Some(entry) = entries.last_mut() else {
+                continue;
+            };
+            let value = match record {
+                "TYPE" => {
+                    entry.kind = fields.first().unwrap_or(&"STANDARD").to_string();
+                    continue;
+                }
+                "GLAS" => {
+                    entry.glass = fields.iter().map(|f| f.to_string()).collect();
+                    continue;
+                }
+                "STOP" => {
+                    entry.stop = true;
+                    continue;
+                }
+                "CURV" | "CONI" | "DISZ" | "DIAM" => number(0),
+                "PARM" => number(1),
+                _ => continue,
+            };
+            let Some(value) = value else {
+                warnings.push(ZmxWarning::BadNumber { line: line_number });
+                continue;
+            };
+            match record {
+                "CURV" => entry.curv = value,
+                "CONI" => entry.conic = value,
+                "DISZ" => entry.thickness = value,
+                "DIAM" => entry.semi_diameter = value,
+                _ => match number(0) {
+                    Some(parm) if parm >= 1.0 => entry.parms.push((parm as usize, value)),
+                    _ => warnings.push(ZmxWarning::BadNumber { line: line_number }),
+                },
+            }
+            continue;
+        }
+
+        match record {
+            "NAME" => name = fields.join(" "),
+            "UNIT" => {
+                let unit = fields.first().unwrap_or(&"MM");
+                scale = match *unit {
+                    "MM" => 1.0,
+                    "CM" => 10.0,
+                    "IN" => 25.4,
+                    "METER" | "M" => 1000.0,
+                    _ => {
+                        warnings.push(ZmxWarning::UnknownUnit(unit.to_string()));
+                        1.0
+                    }
+                };
+            }
+            "SURF" => entries.push(Entry {
+                number: number(0).map_or(entries.len(), |n| n as usize),
+                kind: "STANDARD".to_string(),
+                curv: 0.0,
+                conic: 0.0,
+                parms: vec![],
+                thickness: 0.0,
+                semi_diameter: 0.0,
+                glass: vec![],
+                stop: false,
+            }),
+            "ENPD" | "WAVM" | "PWAV" => {
+                let values = match record {
+                    "WAVM" => (number(1), number(2).or(Some(1.0))),
+                    _ => (number(0), Some(0.0)),
+                };
+                let (Some(value), Some(weight)) = values else {
+                    warnings.push(ZmxWarning::BadNumber { line: line_number });
+                    continue;
+                };
+                match record {
+                    "ENPD" => entrance_pupil_diameter = Some(value),
+                    "WAVM" => {
+                        wavelengths.push(value);
+                        weights.push(weight);
+                    }
+                    _ => primary_wavelength = (value as usize).saturating_sub(1),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if entries.len() < 3 {
+        return Err(ZmxError::TooFewSurfaces);
+    }
+    if wavelengths.is_empty() {
+        warnings.push(ZmxWarning::NoWavelengths);
+    }
+    let primary_wavelength = primary_wavelength.min(wavelengths.len().saturating_sub(1));
+    let mut lens = build_lens(&entries, scale, &mut warnings);
+    if let Some(wavelength) = wavelengths.get(primary_wavelength) {
+        // model nd stays for a glass that does not cover the primary wavelength
+        if let Ok(resolved) = lens.at_wavelength(*wavelength) {
+            lens = resolved;
+        }
+    }
+
+    Ok((
+        ZmxSystem {
+            name,
+            lens,
+            wavelengths,
+            weights,
+            primary_wavelength,
+            entrance_pupil_diameter: entrance_pupil_diameter.map(|d| d * scale),
+        },
+        warnings,
+    ))
+}
+
+// numbers are written in C float formats, INFINITY for an object at infinity
+fn parse_number(field: &str) -> Option<f64> {
+    match field {
+        "INFINITY" | "Infinity" => Some(f64::INFINITY),
+        _ => field.parse::<f64>().ok(),
+    }
+}
+
+fn build_lens(entries: &[Entry], scale: f64, warnings: &mut Vec<ZmxWarning>) -> Lens {
+    let object = &entries[0];
+    let mut surfaces: Vec<Surface> = vec![];
+    let mut stop = None;
+    let mut diameter: f64 = 0.0;
+    let mut object_distance = object.thickness * scale;
+    // the medium a mirror reflects back into
+    let mut medium = (1.0, None);
+    // coordinate break waiting for its surface, and the one applied that should be undone
+    let mut pending: Option<(usize, CoordBreak)> = None;
+    let mut applied: Option<(usize, CoordBreak)> = None;
+
+    for entry in &entries[1..entries.len() - 1] {
+        let thickness = entry.thickness * scale;
+        if entry.kind == "COORDBRK" {
+            let parm = |i: usize| parm_value(entry, i);
+            let deg = std::f64::consts::PI / 180.0;
+            let cb = CoordBreak {
+                dx: parm(1) * scale,
+                dy: parm(2) * scale,
+                alpha: parm(3) * deg,
+                beta: parm(4) * deg,
+                gamma: parm(5) * deg,
+            };
+            let undoes = |(_, last): &(usize, CoordBreak)| {
+                let sum = [
+                    last.dx + cb.dx,
+                    last.dy + cb.dy,
+                    last.alpha + cb.alpha,
+                    last.beta + cb.beta,
+                    last.gamma + cb.gamma,
+                ];
+                sum.iter().all(|v| v.abs() < 1e-12)
+            };
+            match applied.take() {
+                Some(last) if undoes(&last) => {}
+                last => {
+                    if let Some((number, _)) = last.or(pending.take()) {
+                        warnings.push(ZmxWarning::UnsupportedCoordBreak { surface: number });
+                    }
+                    if parm(6) != 0.0 {
+                        warnings.push(ZmxWarning::UnsupportedCoordBreak {
+                            surface: entry.number,
+                        });
+                    }
+                    pending = (!cb.is_identity()).then_some((entry.number, cb));
+                }
+            }
+            // the break's own thickness runs from the surface before it
+            match surfaces.last_mut() {
+                Some(surf) => surf.thickness += thickness,
+                None => object_distance += thickness,
+            }
+            continue;
+        }
+
+        if let Some(last) = applied.take() {
+            warnings.push(ZmxWarning::UnsupportedCoordBreak { surface: last.0 });
+        }
+        let mut surf = Surface::new(side(entry, scale, warnings), thickness, 1.0);
+        if let Some((number, cb)) = pending.take() {
+            surf.coord_break = cb.clone();
+            applied = Some((number, cb));
+        }
+        if entry.semi_diameter > 0.0 {
+            surf.clear_ap = Some(2.0 * entry.semi_diameter * scale);
+            diameter = diameter.max(2.0 * entry.semi_diameter * scale);
+        }
+
+        match entry.glass.first().map(|g| g.as_str()) {
+            Some("MIRROR") => {
+                surf.mirror = true;
+                (surf.n_index, surf.material) = medium.clone();
+            }
+            Some(glass) => {
+                surf.n_index = entry
+                    .glass
+                    .get(3)
+                    .and_then(|nd| nd.parse::<f64>().ok())
+                    .filter(|nd| *nd > 0.0)
+                    .unwrap_or(1.0);
+                surf.material = material_for_glass(glass);
+                if surf.material.is_none() && glass != "___BLANK" {
+                    warnings.push(ZmxWarning::UnknownGlass {
+                        surface: entry.number,
+                        glass: glass.to_string(),
+                    });
+                }
+            }
+            None => {}
+        }
+        medium = (surf.n_index, surf.material.clone());
+
+        if entry.stop {
+            stop = Some(surfaces.len());
+        }
+        surfaces.push(surf);
+    }
+    if let Some((number, _)) = applied.or(pending) {
+        warnings.push(ZmxWarning::UnsupportedCoordBreak { surface: number });
+    }
+
+    Lens {
+        stop,
+        object_distance: object_distance.is_finite().then_some(object_distance),
+        ..Lens::new(diameter, 0.0, surfaces)
+    }
+}
+
+fn parm_value(entry: &Entry, i: usize) -> f64 {
+    entry
+        .parms
+        .iter()
+        .find(|(n, _)| *n == i)
+        .map_or(0.0, |(_, v)| *v)
+}
+
+// Side of a STANDARD, EVENASPH or ODDASPHE surface. A PARM multiplying r^p is in lens units to
+// the power 1 - p.
+fn side(entry: &Entry, scale: f64, warnings: &mut Vec<ZmxWarning>) -> Side {
+    let r = if entry.curv == 0.0 {
+        0.0
+    } else {
+        scale / entry.curv
+    };
+    let unsupported = |parm: usize| ZmxWarning::UnsupportedParameter {
+        surface: entry.number,
+        parm,
+    };
+    let nonzero = entry.parms.iter().filter(|(_, v)| *v != 0.0);
+
+    match entry.kind.as_str() {
+        // PARM i multiplies r^(2i), the tracer terms start at r^4
+        "EVENASPH" => {
+            let mut coeffs = vec![];
+            for (parm, value) in nonzero {
+                if *parm < 2 {
+                    warnings.push(unsupported(*parm));
+                    continue;
+                }
+                let power = 2 * *parm as i32;
+                let idx = *parm - 2;
+                if coeffs.len() <= idx {
+                    coeffs.resize(idx + 1, 0.0);
+                }
+                coeffs[idx] = value * scale.powi(1 - power);
+            }
+            Side::new(r, entry.conic, coeffs)
+        }
+        // PARM i multiplies r^i
+        "ODDASPHE" => {
+            let mut coeffs = vec![];
+            for (parm, value) in nonzero {
+                let idx = *parm - 1;
+                if coeffs.len() <= idx {
+                    coeffs.resize(idx + 1, 0.0);
+                }
+                coeffs[idx] = value * scale.powi(1 - *parm as i32);
+            }
+            Side {
+                definition: AsphereDefinition::Odd,
+                ..Side::new(r, entry.conic, coeffs)
+            }
+        }
+        "STANDARD" => {
+            warnings.extend(nonzero.map(|(parm, _)| unsupported(*parm)));
+            Side::new(r, entry.conic, vec![])
+        }
+        kind => {
+            warnings.push(ZmxWarning::UnsupportedSurface {
+                surface: entry.number,
+                kind: kind.to_string(),
+            });
+            Side::new(r, entry.conic, vec![])
+        }
+    }
+}
+
+// Zemax catalog glass to a material name, the built in names follow Material.fromZemaxGlass in
+// material.ts and any other glass has to be registered from a catalog
+fn material_for_glass(glass: &str) -> Option<String> {
+    let builtin = match glass.to_lowercase().as_str() {
+        "n-bk7" => "Bk7",
+        "baf2" => "BaF2",
+        "caf2" => "CaF2",
+        "cdte" => "CdTe",
+        "cleartran" | "cleartran_old" | "zns_broad" | "zns_ir" | "zns_vis" => "ZnS",
+        "csbr" => "CsBr",
+        "f_silica" | "silica" => "FusedSilica",
+        "gaas" => "GaAs",
+        "germanium" | "ge_long" | "ge_old" => "Ge",
+        "kbr" => "KBr",
+        "kcl" => "KCl",
+        "mgf2" => "MgF2",
+        "mgo" => "MgO",
+        "nacl" => "NaCl",
+        "pbf2" => "PbF2",
+        "sapphire" => "Al2O3",
+        "silicon" => "Silicon",
+        "znse" => "ZnSe",
+        _ => return Material::from_name(glass).ok().map(|_| glass.to_string()),
+    };
+    Some(builtin.to_string())
+}
+
+// material name to the Zemax catalog glass, as Material.toZemaxGlassString in material.ts.
+// Registered catalog glasses already carry their Zemax name.
+fn glass_for_material(material: &str) -> String {
+    match material {
+        "Bk7" => "N-BK7",
+        "FusedSilica" => "SILICA",
+        "Ge" => "GERMANIUM",
+        "Al2O3" => "SAPPHIRE",
+        "ZnS" => "ZNS_BROAD",
+        _ => return material.to_uppercase(),
+    }
+    .to_string()
+}
+
+// Write the system as a ZMX prescription in mm. Surfaces the format cannot describe (Forbes
+// and freeform definitions) are written with their curvature and conic and reported.
+pub fn write_zmx(system: &ZmxSystem) -> Result<(String, Vec<ZmxWarning>), ZmxError> {
+    let lens = &system.lens;
+    let last = lens
+        .surfaces
+        .len()
+        .checked_sub(1)
+        .ok_or(ZmxError::TooFewSurfaces)?;
+    let mut warnings = vec![];
+    let mut lines = vec![
+        "MODE SEQ".to_string(),
+        format!("NAME {}", system.name),
+        "UNIT MM X W X CM MR CPMM".to_string(),
+    ];
+    if let Some(enpd) = system.entrance_pupil_diameter {
+        lines.push(format!("ENPD {}", enpd));
+    }
+    for (i, wavelength) in system.wavelengths.iter().enumerate() {
+        let weight = system.weights.get(i).copied().unwrap_or(1.0);
+        lines.push(format!("WAVM {} {} {}", i + 1, wavelength, weight));
+    }
+    lines.push(format!("PWAV {}", system.primary_wavelength + 1));
+
+    let mut number = 0;
+    let mut surf_lines = |number: &mut usize, records: Vec<String>| {
+        lines.push(format!("SURF {}", number));
+        lines.extend(records.into_iter().map(|r| format!("  {}", r)));
+        *number += 1;
+    };
+    let object = match lens.object_distance {
+        Some(d) => d.to_string(),
+        None => "INFINITY".to_string(),
+    };
+    surf_lines(
+        &mut number,
+        vec![
+            "TYPE STANDARD".to_string(),
+            "CURV 0.0".to_string(),
+            format!("DISZ {}", object),
+        ],
+    );
+
+    for (i, surf) in lens.surfaces.iter().enumerate() {
+        // the image sits at the paraxial focus unless a distance to it was given
+        let thickness = if i == last && surf.thickness == 0.0 {
+            lens.image_distance()
+        } else {
+            surf.thickness
+        };
+        let cb = &surf.coord_break;
+        let tilted = !cb.is_identity();
+        let deg = 180.0 / std::f64::consts::PI;
+        let coordbrk = |sign: f64, order: f64, thickness: f64| {
+            vec![
+                "TYPE COORDBRK".to_string(),
+                format!("PARM 1 {}", sign * cb.dx),
+                format!("PARM 2 {}", sign * cb.dy),
+                format!("PARM 3 {}", sign * cb.alpha * deg),
+                format!("PARM 4 {}", sign * cb.beta * deg),
+                format!("PARM 5 {}", sign * cb.gamma * deg),
+                format!("PARM 6 {}", order),
+                format!("DISZ {}", thickness),
+            ]
+        };
+        if tilted {
+            surf_lines(&mut number, coordbrk(1.0, 0.0, 0.0));
+        }
+
+        let mut records = vec![];
+        if lens.stop == Some(i) {
+            records.push("STOP".to_string());
+        }
+        let side = &surf.side;
+        let (kind, parms) = match side.definition {
+            AsphereDefinition::Standard if side.coeffs.iter().any(|a| *a != 0.0) => (
+                "EVENASPH",
+                (2..).zip(side.coeffs.iter()).collect::<Vec<_>>(),
+            ),
+            AsphereDefinition::Standard => ("STANDARD", vec![]),
+            AsphereDefinition::Odd => ("ODDASPHE", (1..).zip(side.coeffs.iter()).collect()),
+            definition => {
+                warnings.push(ZmxWarning::UnsupportedSurface {
+                    surface: number,
+                    kind: format!("{:?}", definition),
+                });
+                ("STANDARD", vec![])
+            }
+        };
+        records.push(format!("TYPE {}", kind));
+        records.push(format!("CURV {}", side.curv()));
+        if side.k != 0.0 {
+            records.push(format!("CONI {}", side.k));
+        }
+        records.extend(parms.iter().map(|(p, v)| format!("PARM {} {}", p, v)));
+        if let Some(semi) = lens.clear_radius(i) {
+            records.push(format!("DIAM {} 1 0 0", semi));
+        }
+        records.push(format!("DISZ {}", if tilted { 0.0 } else { thickness }));
+        if surf.mirror {
+            records.push("GLAS MIRROR 0 0".to_string());
+        } else if let Some(material) = &surf.material {
+            records.push(format!(
+                "GLAS {} 0 0 {} 0 0 0 0 0 0 0",
+                glass_for_material(material),
+                surf.n_index
+            ));
+        } else if surf.n_index != 1.0 {
+            records.push(format!("GLAS ___BLANK 1 0 {} 0 0 0 0 0 0 0", surf.n_index));
+        }
+        surf_lines(&mut number, records);
+
+        if tilted {
+            surf_lines(&mut number, coordbrk(-1.0, 1.0, thickness));
+        }
+    }
+
+    surf_lines(
+        &mut number,
+        vec![
+            "TYPE STANDARD".to_string(),
+            "CURV 0.0".to_string(),
+            "DISZ 0".to_string(),
+        ],
+    );
+    Ok((lines.join("\n") + "\n", warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SINGLET: &str = "\u{feff}VERS 140404 270 69345
+MODE SEQ
+NAME Test singlet
+UNIT IN X W X CM MR CPMM
+ENPD 0.5
+WAVM 1 0.4861327 1
+WAVM 2 0.5875618 2
+WAVM 3 0.6562725 1
+PWAV 2
+SURF 0
+  TYPE STANDARD
+  CURV 0.0 0 0 0 0 \"\"
+  DISZ INFINITY
+SURF 1
+  STOP
+  TYPE EVENASPH
+  CURV 0.05 0 0 0 0 \"\"
+  CONI -1
+  PARM 1 0.001
+  PARM 2 -1E-4
+  DIAM 0.5 1 0 0 1 \"\"
+  DISZ 0.2
+  GLAS N-BK7 0 0 1.5168 64.17 0 0 0 0 0 0
+SURF 2
+  TYPE TOROIDAL
+  CURV -0.02 0 0 0 0 \"\"
+  DIAM 0.5 1 0 0 1 \"\"
+  DISZ 2
+SURF 3
+  TYPE STANDARD
+  DISZ 1
+  GLAS UNOBTAINIUM 0 0 1.7 30 0 0 0 0 0 0
+SURF 4
+  TYPE STANDARD
+  CURV 0.0
+  DISZ 0
+";
+
+    #[test]
+    fn parses_singlet() {
+        let (system, warnings) = parse_zmx(SINGLET).unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                ZmxWarning::UnsupportedParameter {
+                    surface: 1,
+                    parm: 1
+                },
+                ZmxWarning::UnsupportedSurface {
+                    surface: 2,
+                    kind: "TOROIDAL".to_string()
+                },
+                ZmxWarning::UnknownGlass {
+                    surface: 3,
+                    glass: "UNOBTAINIUM".to_string()
+                },
+            ]
+        );
+        assert_eq!(system.name, "Test singlet");
+        assert_eq!(system.wavelengths, vec![0.4861327, 0.5875618, 0.6562725]);
+        assert_eq!(system.weights, vec![1.0, 2.0, 1.0]);
+        assert_eq!(system.primary_wavelength, 1);
+        assert_eq!(system.entrance_pupil_diameter, Some(12.7));
+
+        // inches converted to mm
+        let lens = &system.lens;
+        assert_eq!(lens.surfaces.len(), 3);
+        assert_eq!(lens.stop, Some(0));
+        assert_eq!(lens.object_distance, None);
+        let s1 = &lens.surfaces[0];
+        assert!((s1.side.r - 508.0).abs() < 1e-9);
+        assert_eq!(s1.side.k, -1.0);
+        assert!((s1.side.coeffs[0] + 1e-4 / 25.4_f64.powi(3)).abs() < 1e-20);
+        assert!((s1.thickness - 5.08).abs() < 1e-12);
+        assert!((s1.clear_ap.unwrap() - 25.4).abs() < 1e-12);
+        assert_eq!(lens.diameter, 25.4);
+
+        // the catalog glass is resolved at the primary wavelength, the unknown one keeps nd
+        assert_eq!(s1.material.as_deref(), Some("Bk7"));
+        assert!((s1.n_index - 1.5168).abs() < 1e-4);
+        assert_eq!(lens.surfaces[2].material, None);
+        assert_eq!(lens.surfaces[2].n_index, 1.7);
+
+        assert_eq!(
+            parse_zmx("SURF 0\nSURF 1\n").err(),
+            Some(ZmxError::TooFewSurfaces)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut fold = Surface::new(Side::new(0., 0., vec![]), -40., 1.);
+        fold.mirror = true;
+        fold.coord_break.alpha = 0.1;
+        fold.coord_break.dy = 0.5;
+        let mut lens = Lens::new(
+            20.,
+            0.,
+            vec![
+                Surface {
+                    material: Some("Bk7".to_string()),
+                    ..Surface::new(Side::new(60., -0.5, vec![1e-6, 0., 2e-10]), 4., 1.5168)
+                },
+                Surface::new(
+                    Side {
+                        definition: AsphereDefinition::Odd,
+                        ..Side::new(-60., 0., vec![0., 0., 3e-6])
+                    },
+                    30.,
+                    1.,
+                ),
+                fold,
+                Surface::new(Side::new(0., 0., vec![]), 0., 1.),
+            ],
+        );
+        lens.stop = Some(0);
+        lens.object_distance = Some(200.);
+        let system = ZmxSystem {
+            name: "round trip".to_string(),
+            lens,
+            wavelengths: vec![0.5875618],
+            weights: vec![1.0],
+            primary_wavelength: 0,
+            entrance_pupil_diameter: Some(10.),
+        };
+
+        let (text, warnings) = write_zmx(&system).unwrap();
+        assert!(warnings.is_empty());
+        let (read, warnings) = parse_zmx(&text).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        let (a, b) = (&system.lens, &read.lens);
+        assert_eq!(b.surfaces.len(), a.surfaces.len());
+        assert_eq!(b.stop, a.stop);
+        assert_eq!(b.object_distance, a.object_distance);
+        for (sa, sb) in a.surfaces[..3].iter().zip(&b.surfaces) {
+            assert!((sa.side.r - sb.side.r).abs() < 1e-9);
+            assert_eq!(sa.side.k, sb.side.k);
+            assert_eq!(sa.side.coeffs, sb.side.coeffs);
+            assert_eq!(sa.side.definition, sb.side.definition);
+            assert_eq!(sa.thickness, sb.thickness);
+            assert_eq!(sa.mirror, sb.mirror);
+            assert_eq!(sa.material, sb.material);
+            assert!((sa.coord_break.alpha - sb.coord_break.alpha).abs() < 1e-12);
+            assert!((sa.coord_break.dy - sb.coord_break.dy).abs() < 1e-12);
+        }
+        // the image follows the last surface at the paraxial focus
+        assert!((b.surfaces[3].thickness - a.image_distance()).abs() < 1e-9);
+        assert_eq!(read.entrance_pupil_diameter, Some(10.));
+
+        let empty = ZmxSystem {
+            lens: Lens::new(20., 0., vec![]),
+            ..system
+        };
+        assert_eq!(write_zmx(&empty).err(), Some(ZmxError::TooFewSurfaces));
+    }
+}